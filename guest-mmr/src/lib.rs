@@ -0,0 +1,359 @@
+//! The MMR appended to by the zkVM guest. It only keeps the peaks it starts
+//! from and the nodes it creates, and is shared with the host so that both
+//! run the same code.
+//!
+//! The crate depends on `std`, which the zkVM guest provides: `no_std` builds
+//! are out of scope.
+pub use guest_types::inclusion::find_peaks;
+use guest_types::{
+    felt,
+    inclusion::{bag_peaks, calculate_root_hash, elements_count_to_leaf_count, root_from_bag},
+    AppendMode, AppendResult, GuestInput, GuestOutput, HasherKind, KeccakHasher, MmrHasher,
+    MmrNode, PeaksFormattingOptions, PeaksOptions, PhaseCycles, PoseidonHasher, Sha256Hasher,
+};
+use starknet_crypto::Felt;
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum FormattingError {
+    #[error("Formatting: Expected peaks output size is smaller than the actual size")]
+    PeaksOutputSizeError,
+}
+
+#[derive(Error, Debug)]
+pub enum MMRError {
+    NoHashFoundForIndex(usize),
+    Formatting(FormattingError),
+    InsufficientPeaksForMerge,
+    HashError,
+    InvalidElement(String),
+    InvalidInitialState {
+        elements_count: usize,
+        leaves_count: usize,
+        peaks_count: usize,
+    },
+}
+
+pub struct GuestMMR<H: MmrHasher = PoseidonHasher> {
+    hashes: BTreeMap<usize, Felt>,
+    elements_count: usize,
+    leaves_count: usize,
+    // Nodes inserted since initialization, in insertion order
+    new_nodes: Vec<MmrNode>,
+    hasher: PhantomData<H>,
+}
+
+impl GuestMMR {
+    pub fn new(
+        initial_peaks: Vec<Felt>,
+        elements_count: usize,
+        leaves_count: usize,
+    ) -> Result<Self, MMRError> {
+        Self::with_hasher(initial_peaks, elements_count, leaves_count)
+    }
+}
+
+impl<H: MmrHasher> GuestMMR<H> {
+    /// Same as `GuestMMR::new`, for an MMR hashed with `H`.
+    ///
+    /// Fails unless `elements_count` is a valid MMR size with `leaves_count`
+    /// leaves and `initial_peaks` holds exactly one hash per peak.
+    pub fn with_hasher(
+        initial_peaks: Vec<Felt>,
+        elements_count: usize,
+        leaves_count: usize,
+    ) -> Result<Self, MMRError> {
+        let peak_positions = find_peaks(elements_count);
+        let valid_size = elements_count == 0 || !peak_positions.is_empty();
+        if !valid_size
+            || initial_peaks.len() != peak_positions.len()
+            || elements_count_to_leaf_count(elements_count) != Ok(leaves_count)
+        {
+            return Err(MMRError::InvalidInitialState {
+                elements_count,
+                leaves_count,
+                peaks_count: initial_peaks.len(),
+            });
+        }
+
+        // Initialize hashes with the peaks at their correct positions
+        let hashes = peak_positions.into_iter().zip(initial_peaks).collect();
+
+        Ok(Self {
+            elements_count,
+            leaves_count,
+            hashes,
+            new_nodes: Vec::new(),
+            hasher: PhantomData,
+        })
+    }
+
+    pub fn get_elements_count(&self) -> usize {
+        self.elements_count
+    }
+
+    pub fn get_leaves_count(&self) -> usize {
+        self.leaves_count
+    }
+
+    pub fn get_new_nodes(&self) -> &[MmrNode] {
+        &self.new_nodes
+    }
+
+    /// Hash at `element_index`, if it is one of the initial peaks or was
+    /// created by this MMR.
+    pub fn get_hash(&self, element_index: usize) -> Option<Felt> {
+        self.hashes.get(&element_index).copied()
+    }
+
+    pub fn append(&mut self, value: Felt) -> Result<AppendResult, MMRError> {
+        let elements_count = self.elements_count;
+
+        let mut peaks = self.retrieve_peaks_hashes(find_peaks(elements_count))?;
+
+        let mut last_element_idx = self.elements_count + 1;
+        let leaf_element_index = last_element_idx;
+
+        // Store the new leaf in the hash map
+        self.hashes.insert(last_element_idx, value);
+        self.new_nodes.push(MmrNode {
+            index: last_element_idx,
+            hash: value,
+        });
+
+        peaks.push(value);
+
+        let no_merges = leaf_count_to_append_no_merges(self.leaves_count);
+
+        for _ in 0..no_merges {
+            if peaks.len() < 2 {
+                return Err(MMRError::InsufficientPeaksForMerge);
+            }
+
+            last_element_idx += 1;
+
+            // Pop the last two peaks to merge
+            let right_hash = peaks.pop().unwrap();
+            let left_hash = peaks.pop().unwrap();
+
+            let parent_hash = hash::<H>(&[left_hash, right_hash])?;
+            self.hashes.insert(last_element_idx, parent_hash);
+            self.new_nodes.push(MmrNode {
+                index: last_element_idx,
+                hash: parent_hash,
+            });
+
+            peaks.push(parent_hash);
+        }
+
+        self.elements_count = last_element_idx;
+        self.leaves_count += 1;
+
+        let root_hash = calculate_root_hash(H::KIND, &peaks, last_element_idx);
+
+        Ok(AppendResult {
+            leaves_count: self.leaves_count,
+            elements_count: last_element_idx,
+            element_index: leaf_element_index,
+            root_hash,
+        })
+    }
+
+    fn retrieve_peaks_hashes(&self, peak_idxs: Vec<usize>) -> Result<Vec<Felt>, MMRError> {
+        let mut peaks = Vec::new();
+
+        for &idx in &peak_idxs {
+            // Use `idx` directly since `self.hashes` expects a `usize` key
+            if let Some(hash) = self.hashes.get(&idx) {
+                peaks.push(*hash);
+            } else {
+                return Err(MMRError::NoHashFoundForIndex(idx));
+            }
+        }
+
+        Ok(peaks)
+    }
+
+    pub fn bag_the_peaks(&self) -> Result<Felt, MMRError> {
+        let peaks_hashes = self.retrieve_peaks_hashes(find_peaks(self.elements_count))?;
+        Ok(bag_peaks(H::KIND, &peaks_hashes))
+    }
+
+    pub fn calculate_root_hash(&self, bag: &Felt, elements_count: usize) -> Result<Felt, MMRError> {
+        Ok(root_from_bag(H::KIND, *bag, elements_count))
+    }
+
+    pub fn get_peaks(&self, option: PeaksOptions) -> Result<Vec<Felt>, MMRError> {
+        let tree_size = match option.elements_count {
+            Some(count) => count,
+            None => self.elements_count,
+        };
+
+        let peaks_indices = find_peaks(tree_size);
+        let peaks = self.retrieve_peaks_hashes(peaks_indices)?;
+
+        if let Some(formatting_opts) = option.formatting_opts {
+            match format_peaks(peaks, &formatting_opts) {
+                Ok(formatted_peaks) => Ok(formatted_peaks),
+                Err(e) => Err(MMRError::Formatting(e)),
+            }
+        } else {
+            Ok(peaks)
+        }
+    }
+}
+
+impl std::fmt::Display for MMRError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MMRError::NoHashFoundForIndex(idx) => write!(f, "No hash found for index {}", idx),
+            MMRError::Formatting(e) => write!(f, "Formatting error: {}", e),
+            MMRError::InsufficientPeaksForMerge => write!(f, "Insufficient peaks for merge"),
+            MMRError::HashError => write!(f, "Hash error"),
+            MMRError::InvalidElement(e) => write!(f, "Invalid element: {}", e),
+            MMRError::InvalidInitialState {
+                elements_count,
+                leaves_count,
+                peaks_count,
+            } => write!(
+                f,
+                "Invalid initial state: {} elements, {} leaves and {} peaks",
+                elements_count, leaves_count, peaks_count
+            ),
+        }
+    }
+}
+
+pub fn format_peaks(
+    mut peaks: Vec<Felt>,
+    formatting_opts: &PeaksFormattingOptions,
+) -> Result<Vec<Felt>, FormattingError> {
+    if peaks.len() > formatting_opts.output_size {
+        return Err(FormattingError::PeaksOutputSizeError);
+    }
+
+    let expected_peaks_size_remainder = formatting_opts.output_size - peaks.len();
+    let peaks_null_values: Vec<Felt> =
+        vec![formatting_opts.null_value; expected_peaks_size_remainder];
+
+    peaks.extend(peaks_null_values);
+
+    Ok(peaks)
+}
+
+/// Parses an element given as a hex string, rejecting malformed values and
+/// values that do not fit in a felt.
+pub fn parse_element(value: &str) -> Result<Felt, MMRError> {
+    felt::from_hex(value).ok_or_else(|| MMRError::InvalidElement(value.to_string()))
+}
+
+/// Element of a 256 bit block hash in an MMR built with `hasher`, see
+/// `felt::block_hash_to_felt`.
+pub fn block_hash_element(hasher: HasherKind, block_hash: &str) -> Result<Felt, MMRError> {
+    felt::block_hash_to_felt(hasher, block_hash)
+        .ok_or_else(|| MMRError::InvalidElement(block_hash.to_string()))
+}
+
+/// Append the elements of `mmr_input` to the MMR it starts from, hashing with
+/// `hasher`, and build the resulting batch output. The elements are block
+/// hashes if the batch has a `block_range`, generic leaves otherwise.
+pub fn append_batch(
+    hasher: HasherKind,
+    mmr_input: GuestInput,
+    block_range: Option<(u64, u64)>,
+) -> Result<GuestOutput, MMRError> {
+    append_batch_profiled(hasher, mmr_input, block_range, || 0).map(|(output, _)| output)
+}
+
+/// Same as `append_batch`, also measuring the cycles spent on the appends and
+/// on the peak extraction with `cycle_count`. The other phases are left at 0.
+pub fn append_batch_profiled(
+    hasher: HasherKind,
+    mmr_input: GuestInput,
+    block_range: Option<(u64, u64)>,
+    cycle_count: fn() -> u64,
+) -> Result<(GuestOutput, PhaseCycles), MMRError> {
+    match hasher {
+        HasherKind::Poseidon => {
+            append_batch_with::<PoseidonHasher>(mmr_input, block_range, cycle_count)
+        }
+        HasherKind::Keccak => {
+            append_batch_with::<KeccakHasher>(mmr_input, block_range, cycle_count)
+        }
+        HasherKind::Sha256 => {
+            append_batch_with::<Sha256Hasher>(mmr_input, block_range, cycle_count)
+        }
+    }
+}
+
+fn append_batch_with<H: MmrHasher>(
+    mmr_input: GuestInput,
+    block_range: Option<(u64, u64)>,
+    cycle_count: fn() -> u64,
+) -> Result<(GuestOutput, PhaseCycles), MMRError> {
+    let mut mmr = GuestMMR::<H>::with_hasher(
+        mmr_input.initial_peaks.clone(),
+        mmr_input.elements_count,
+        mmr_input.leaves_count,
+    )?;
+    let chained = mmr_input.starts_empty();
+
+    let start = cycle_count();
+    let append_results = mmr_input
+        .new_elements
+        .iter()
+        .map(|element| mmr.append(*element))
+        .collect::<Result<Vec<_>, _>>()?;
+    let appended = cycle_count();
+    let final_peaks = mmr.get_peaks(PeaksOptions::default())?;
+    let cycles = PhaseCycles {
+        mmr_appends: appended - start,
+        peak_extraction: cycle_count() - appended,
+        ..Default::default()
+    };
+
+    let mode = match block_range {
+        Some(_) => AppendMode::BlockHashes,
+        None => AppendMode::GenericLeaves,
+    };
+    let output = GuestOutput {
+        initial_peaks: mmr_input.initial_peaks,
+        initial_elements_count: mmr_input.elements_count,
+        initial_leaves_count: mmr_input.leaves_count,
+        final_peaks,
+        elements_count: mmr.get_elements_count(),
+        leaves_count: mmr.get_leaves_count(),
+        mode,
+        block_range,
+        // Extended with the blocks of the previous proof by the guest
+        chain_block_range: block_range,
+        hasher: H::KIND,
+        method_id: mmr_input.method_id,
+        aggregation_method_id: mmr_input.aggregation_method_id,
+        // A batch continuing a non-empty MMR is only chained through the
+        // previous proof, which the guest verifies
+        chained,
+        append_results,
+        new_nodes: mmr.new_nodes,
+    };
+
+    Ok((output, cycles))
+}
+
+fn leaf_count_to_append_no_merges(leaf_count: usize) -> usize {
+    if leaf_count == 0 {
+        return 0;
+    }
+    (!leaf_count).trailing_zeros() as usize
+}
+
+fn hash<H: MmrHasher>(data: &[Felt]) -> Result<Felt, MMRError> {
+    match data.len() {
+        0 => Err(MMRError::HashError),
+        1 => Ok(H::hash_single(data[0])),
+        2 => Ok(H::hash_pair(data[0], data[1])),
+        _ => Ok(H::hash_many(data)),
+    }
+}
@@ -0,0 +1,202 @@
+use crate::{felt, AppendMode, BatchProof, CompactGuestOutput, GuestOutput, HasherKind};
+use serde::{Deserialize, Serialize};
+use starknet_crypto::Felt;
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum AggregationError {
+    #[error("No batches to aggregate")]
+    Empty,
+    #[error("Batch {0} does not continue the batch before it")]
+    Disconnected(usize),
+}
+
+/// Start and end state of the MMR across a batch, or a run of batches, in
+/// either journal format.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatchTransition {
+    #[serde(with = "felt::vec")]
+    pub initial_peaks: Vec<Felt>,
+    pub initial_elements_count: usize,
+    pub initial_leaves_count: usize,
+    #[serde(with = "felt::vec")]
+    pub final_peaks: Vec<Felt>,
+    pub elements_count: usize,
+    pub leaves_count: usize,
+    /// `GuestOutput::mode`
+    pub mode: AppendMode,
+    /// Lowest and highest block number, `None` for generic leaves
+    pub block_range: Option<(u64, u64)>,
+    /// `GuestOutput::chain_block_range`
+    pub chain_block_range: Option<(u64, u64)>,
+    pub hasher: HasherKind,
+    /// Image ID of the batch guest, see `GuestInput::method_id`
+    pub method_id: [u32; 8],
+    /// `GuestInput::aggregation_method_id`
+    pub aggregation_method_id: [u32; 8],
+    /// Whether the history before the first batch is proven, see
+    /// `GuestOutput::chained`
+    pub chained: bool,
+}
+
+impl BatchTransition {
+    /// Returns true if this transition ends in the MMR state described by the
+    /// given peaks and counts, i.e. a batch starting from that state continues
+    /// it.
+    pub fn ends_at(&self, peaks: &[Felt], elements_count: usize, leaves_count: usize) -> bool {
+        self.final_peaks == peaks
+            && self.elements_count == elements_count
+            && self.leaves_count == leaves_count
+    }
+
+    /// Returns true if `next` starts from the state this transition ends in,
+    /// with the same hasher, guests and append mode, and covers the blocks right above
+    /// or right below every block of this chain, for forward and backward
+    /// builds. Blocks already in the chain can never be appended again.
+    pub fn connects_to(&self, next: &BatchTransition) -> bool {
+        let adjacent_blocks = match (self.chain_block_range, next.block_range) {
+            (None, None) => true,
+            _ => self.extension_of(next).is_some(),
+        };
+
+        adjacent_blocks
+            && self.mode == next.mode
+            && self.hasher == next.hasher
+            && self.method_id == next.method_id
+            && self.aggregation_method_id == next.aggregation_method_id
+            && self.ends_at(
+                &next.initial_peaks,
+                next.initial_elements_count,
+                next.initial_leaves_count,
+            )
+    }
+
+    /// Which end of the blocks of this chain `next` extends, `None` if its
+    /// blocks are not right above or right below them.
+    fn extension_of(&self, next: &BatchTransition) -> Option<Extension> {
+        let ((start, end), (next_start, next_end)) = (self.chain_block_range?, next.block_range?);
+        if next_start == end + 1 {
+            Some(Extension::Above)
+        } else if next_end + 1 == start {
+            Some(Extension::Below)
+        } else {
+            None
+        }
+    }
+}
+
+/// Side of the chained blocks a batch appends to: above them for forward
+/// builds, below them for backward builds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Extension {
+    Above,
+    Below,
+}
+
+impl From<&GuestOutput> for BatchTransition {
+    fn from(output: &GuestOutput) -> Self {
+        Self {
+            initial_peaks: output.initial_peaks.clone(),
+            initial_elements_count: output.initial_elements_count,
+            initial_leaves_count: output.initial_leaves_count,
+            final_peaks: output.final_peaks.clone(),
+            elements_count: output.elements_count,
+            leaves_count: output.leaves_count,
+            mode: output.mode,
+            block_range: output.block_range,
+            chain_block_range: output.chain_block_range,
+            hasher: output.hasher,
+            method_id: output.method_id,
+            aggregation_method_id: output.aggregation_method_id,
+            chained: output.chained,
+        }
+    }
+}
+
+impl From<GuestOutput> for BatchTransition {
+    fn from(output: GuestOutput) -> Self {
+        Self::from(&output)
+    }
+}
+
+impl From<CompactGuestOutput> for BatchTransition {
+    fn from(output: CompactGuestOutput) -> Self {
+        Self {
+            initial_peaks: output.initial_peaks,
+            initial_elements_count: output.initial_elements_count,
+            initial_leaves_count: output.initial_leaves_count,
+            final_peaks: output.final_peaks,
+            elements_count: output.elements_count,
+            leaves_count: output.leaves_count,
+            mode: output.mode,
+            block_range: output.block_range,
+            chain_block_range: output.chain_block_range,
+            hasher: output.hasher,
+            method_id: output.method_id,
+            aggregation_method_id: output.aggregation_method_id,
+            chained: output.chained,
+        }
+    }
+}
+
+/// The transition spanned by `transitions`, which must follow each other.
+/// Each one must continue the blocks of all the ones before it, on the side
+/// the second one picked, so that no block is appended twice.
+pub fn chain_transitions(
+    transitions: &[BatchTransition],
+) -> Result<BatchTransition, AggregationError> {
+    let (first, rest) = transitions.split_first().ok_or(AggregationError::Empty)?;
+
+    let mut chained = first.clone();
+    let mut direction = None;
+    for (i, next) in rest.iter().enumerate() {
+        let extension = chained.extension_of(next);
+        if !chained.connects_to(next) || (direction.is_some() && extension != direction) {
+            return Err(AggregationError::Disconnected(i + 1));
+        }
+        direction = extension;
+
+        chained.final_peaks = next.final_peaks.clone();
+        chained.elements_count = next.elements_count;
+        chained.leaves_count = next.leaves_count;
+        chained.block_range = merge_block_ranges(chained.block_range, next.block_range);
+        chained.chain_block_range =
+            merge_block_ranges(chained.chain_block_range, next.chain_block_range);
+    }
+
+    Ok(chained)
+}
+
+/// Smallest block range covering both ranges, `None` if either is `None`.
+pub fn merge_block_ranges(
+    range: Option<(u64, u64)>,
+    other: Option<(u64, u64)>,
+) -> Option<(u64, u64)> {
+    match (range, other) {
+        (Some((start, end)), Some((other_start, other_end))) => {
+            Some((start.min(other_start), end.max(other_end)))
+        }
+        _ => None,
+    }
+}
+
+/// Input of the aggregation guest: batches proven independently, each from
+/// the state the one before it ends in.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AggregationInput {
+    /// Image ID of the batch guest, which every batch must be proven with
+    pub batch_method_id: [u32; 8],
+    /// Batch preceding the first one, if the MMR was not empty
+    pub previous_proof: Option<BatchProof>,
+    /// Batches in append order; their receipts are supplied as assumptions
+    pub batches: Vec<BatchProof>,
+}
+
+/// Journal of the aggregation guest.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AggregationOutput {
+    pub batch_method_id: [u32; 8],
+    pub batch_count: usize,
+    /// Transition from the start of the first batch to the end of the last
+    pub transition: BatchTransition,
+}
@@ -0,0 +1,157 @@
+//! Conversions between `Felt` and hex strings, and felt serialization.
+//! Human readable formats get the `0x` prefixed hex strings previously used
+//! for hashes, binary formats such as journals get the 32 big endian bytes.
+//! Use with `#[serde(with = "felt")]`, or `#[serde(with = "felt::vec")]` for a
+//! `Vec<Felt>`.
+use crate::HasherKind;
+use core::fmt;
+use serde::{
+    de::{Error, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serializer,
+};
+use starknet_crypto::Felt;
+
+pub fn serialize<S: Serializer>(value: &Felt, serializer: S) -> Result<S::Ok, S::Error> {
+    if serializer.is_human_readable() {
+        serializer.serialize_str(&to_hex(value))
+    } else {
+        serializer.serialize_bytes(&value.to_bytes_be())
+    }
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Felt, D::Error> {
+    if deserializer.is_human_readable() {
+        let value = String::deserialize(deserializer)?;
+        from_hex(&value).ok_or_else(|| D::Error::custom(format!("Invalid felt: {}", value)))
+    } else {
+        deserializer.deserialize_bytes(BytesVisitor)
+    }
+}
+
+/// Reads the 32 big endian bytes of a felt, rejecting values that are not
+/// below the field prime.
+struct BytesVisitor;
+
+impl<'de> Visitor<'de> for BytesVisitor {
+    type Value = Felt;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("32 big endian bytes of a felt")
+    }
+
+    fn visit_bytes<E: Error>(self, value: &[u8]) -> Result<Felt, E> {
+        let bytes: [u8; 32] = value
+            .try_into()
+            .map_err(|_| E::invalid_length(value.len(), &self))?;
+        from_bytes(bytes).ok_or_else(|| E::custom("Felt is not below the field prime"))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Felt, A::Error> {
+        let mut bytes = Vec::with_capacity(32);
+        while let Some(byte) = seq.next_element::<u8>()? {
+            bytes.push(byte);
+        }
+        self.visit_bytes(&bytes)
+    }
+}
+
+/// `0x` prefixed lowercase hex, without leading zeros.
+pub fn to_hex(value: &Felt) -> String {
+    format!("0x{:x}", value)
+}
+
+/// Parses a hex string, with or without `0x` prefix, into a felt. Unlike
+/// `Felt::from_hex`, malformed strings and values that are not below the
+/// field prime are rejected instead of being reduced.
+pub fn from_hex(value: &str) -> Option<Felt> {
+    from_bytes(hex_to_bytes32(value)?)
+}
+
+fn from_bytes(bytes: [u8; 32]) -> Option<Felt> {
+    let felt = Felt::from_bytes_be(&bytes);
+    // `from_bytes_be` reduces modulo the prime, reject values that wrapped
+    (felt.to_bytes_be() == bytes).then_some(felt)
+}
+
+/// Leaf encoding of a 256 bit keccak block hash, which does not fit in a
+/// felt: the hash of its high and low 128 bit halves with the MMR's hasher.
+/// The hash must be exactly 32 bytes.
+pub fn block_hash_to_felt(hasher: HasherKind, block_hash: &str) -> Option<Felt> {
+    if strip_hex_prefix(block_hash).len() != 64 {
+        return None;
+    }
+    let bytes = hex_to_bytes32(block_hash)?;
+    let (high, low) = bytes.split_at(16);
+    let high = u128::from_be_bytes(high.try_into().ok()?);
+    let low = u128::from_be_bytes(low.try_into().ok()?);
+    Some(hasher.hash_pair(Felt::from(high), Felt::from(low)))
+}
+
+/// The elements count hashed into the MMR root. Its decimal digits are read
+/// as hex, e.g. 10 elements are hashed as 0x10, as the original string based
+/// implementation did. Kept for compatibility with existing roots.
+pub fn elements_count_to_felt(elements_count: usize) -> Felt {
+    let value = elements_count
+        .to_string()
+        .bytes()
+        .fold(0u128, |acc, digit| (acc << 4) | u128::from(digit - b'0'));
+    Felt::from(value)
+}
+
+/// `value` without its `0x` or `0X` prefix, if it has one.
+pub fn strip_hex_prefix(value: &str) -> &str {
+    value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+        .unwrap_or(value)
+}
+
+/// Big endian bytes of a hex string of at most 64 digits.
+fn hex_to_bytes32(value: &str) -> Option<[u8; 32]> {
+    let digits = strip_hex_prefix(value);
+    if digits.is_empty() || digits.len() > 64 {
+        return None;
+    }
+
+    let mut bytes = [0u8; 32];
+    for (i, digit) in digits.bytes().rev().enumerate() {
+        let nibble = char::from(digit).to_digit(16)? as u8;
+        bytes[31 - i / 2] |= nibble << (4 * (i % 2));
+    }
+    Some(bytes)
+}
+
+pub mod vec {
+    use serde::{ser::SerializeSeq, Deserialize, Deserializer, Serialize, Serializer};
+    use starknet_crypto::Felt;
+
+    /// A felt serialized like a `#[serde(with = "felt")]` field.
+    struct Element(Felt);
+
+    impl Serialize for Element {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            super::serialize(&self.0, serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Element {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            super::deserialize(deserializer).map(Element)
+        }
+    }
+
+    pub fn serialize<S: Serializer>(values: &[Felt], serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(values.len()))?;
+        for value in values {
+            seq.serialize_element(&Element(*value))?;
+        }
+        seq.end()
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Felt>, D::Error> {
+        Ok(Vec::<Element>::deserialize(deserializer)?
+            .into_iter()
+            .map(|element| element.0)
+            .collect())
+    }
+}
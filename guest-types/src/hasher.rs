@@ -0,0 +1,144 @@
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use sha3::{Digest, Keccak256};
+use starknet_crypto::{poseidon_hash, poseidon_hash_many, poseidon_hash_single, Felt};
+use std::fmt;
+use std::str::FromStr;
+
+/// Hash function used for the nodes, the bagging of the peaks and the root of
+/// an MMR.
+pub trait MmrHasher {
+    const KIND: HasherKind;
+
+    fn hash_single(value: Felt) -> Felt;
+
+    fn hash_pair(left: Felt, right: Felt) -> Felt;
+
+    fn hash_many(values: &[Felt]) -> Felt;
+}
+
+/// StarkNet Poseidon, cheap to verify on Starknet.
+pub struct PoseidonHasher;
+
+/// Keccak-256, cheap to verify on the EVM.
+pub struct KeccakHasher;
+
+/// SHA-256, accelerated in the zkVM.
+pub struct Sha256Hasher;
+
+impl MmrHasher for PoseidonHasher {
+    const KIND: HasherKind = HasherKind::Poseidon;
+
+    fn hash_single(value: Felt) -> Felt {
+        poseidon_hash_single(value)
+    }
+
+    fn hash_pair(left: Felt, right: Felt) -> Felt {
+        poseidon_hash(left, right)
+    }
+
+    fn hash_many(values: &[Felt]) -> Felt {
+        poseidon_hash_many(values)
+    }
+}
+
+impl MmrHasher for KeccakHasher {
+    const KIND: HasherKind = HasherKind::Keccak;
+
+    fn hash_single(value: Felt) -> Felt {
+        Self::hash_many(&[value])
+    }
+
+    fn hash_pair(left: Felt, right: Felt) -> Felt {
+        Self::hash_many(&[left, right])
+    }
+
+    fn hash_many(values: &[Felt]) -> Felt {
+        digest_to_felt::<Keccak256>(values)
+    }
+}
+
+impl MmrHasher for Sha256Hasher {
+    const KIND: HasherKind = HasherKind::Sha256;
+
+    fn hash_single(value: Felt) -> Felt {
+        Self::hash_many(&[value])
+    }
+
+    fn hash_pair(left: Felt, right: Felt) -> Felt {
+        Self::hash_many(&[left, right])
+    }
+
+    fn hash_many(values: &[Felt]) -> Felt {
+        digest_to_felt::<Sha256>(values)
+    }
+}
+
+/// Hashes the 32 byte big endian encoding of each value and truncates the
+/// digest to its low 251 bits so that it fits in a felt.
+fn digest_to_felt<D: Digest>(values: &[Felt]) -> Felt {
+    let mut hasher = D::new();
+    for value in values {
+        hasher.update(value.to_bytes_be());
+    }
+
+    let mut bytes = [0u8; 32];
+    bytes.copy_from_slice(&hasher.finalize()[..32]);
+    bytes[0] &= 0x07;
+    Felt::from_bytes_be(&bytes)
+}
+
+/// The `MmrHasher` an MMR was built with, recorded in the batch journals and
+/// inclusion proofs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HasherKind {
+    #[default]
+    Poseidon,
+    Keccak,
+    Sha256,
+}
+
+impl HasherKind {
+    pub fn hash_pair(self, left: Felt, right: Felt) -> Felt {
+        match self {
+            HasherKind::Poseidon => PoseidonHasher::hash_pair(left, right),
+            HasherKind::Keccak => KeccakHasher::hash_pair(left, right),
+            HasherKind::Sha256 => Sha256Hasher::hash_pair(left, right),
+        }
+    }
+
+    pub fn hash_many(self, values: &[Felt]) -> Felt {
+        match self {
+            HasherKind::Poseidon => PoseidonHasher::hash_many(values),
+            HasherKind::Keccak => KeccakHasher::hash_many(values),
+            HasherKind::Sha256 => Sha256Hasher::hash_many(values),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            HasherKind::Poseidon => "poseidon",
+            HasherKind::Keccak => "keccak",
+            HasherKind::Sha256 => "sha256",
+        }
+    }
+}
+
+impl fmt::Display for HasherKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for HasherKind {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "poseidon" => Ok(HasherKind::Poseidon),
+            "keccak" => Ok(HasherKind::Keccak),
+            "sha256" => Ok(HasherKind::Sha256),
+            _ => Err(format!("Unknown hasher: {}", name)),
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use crate::felt::strip_hex_prefix;
+use block_validity::BlockHeader;
+use sha3::{Digest, Keccak256};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum HeaderError {
+    #[error("Missing header field: {0}")]
+    MissingField(&'static str),
+    #[error("Invalid hex in header field: {0}")]
+    InvalidHex(&'static str),
+    #[error("Header field {0} is longer than {1} bytes")]
+    FieldTooLong(&'static str, usize),
+    #[error("Header field {0} is set but an earlier fork field is missing")]
+    UnexpectedField(&'static str),
+}
+
+/// Computes the keccak256 hash of the RLP encoded header, as a `0x` prefixed
+/// lowercase hex string.
+///
+/// The fields after `nonce` are only present from the fork that introduced
+/// them: `base_fee_per_gas` (London), `withdrawals_root` (Shanghai),
+/// `blob_gas_used`, `excess_blob_gas` and `parent_beacon_block_root` (Cancun)
+/// and `requests_hash` (Prague). `requests_hash` is not part of `BlockHeader`
+/// and is passed separately.
+pub fn compute_block_hash(
+    header: &BlockHeader,
+    requests_hash: Option<&str>,
+) -> Result<String, HeaderError> {
+    let encoded = encode_header(header, requests_hash)?;
+    let hash = Keccak256::digest(encoded);
+    Ok(format!("0x{}", hex::encode(hash)))
+}
+
+/// Returns true if both hex strings encode the same hash, ignoring case.
+pub fn block_hashes_match(a: &str, b: &str) -> bool {
+    strip_hex_prefix(a).eq_ignore_ascii_case(strip_hex_prefix(b))
+}
+
+pub fn encode_header(
+    header: &BlockHeader,
+    requests_hash: Option<&str>,
+) -> Result<Vec<u8>, HeaderError> {
+    let mut fields: Vec<Vec<u8>> = vec![
+        fixed_field(&header.parent_hash, 32, "parent_hash")?,
+        fixed_field(&header.sha3_uncles, 32, "sha3_uncles")?,
+        fixed_field(&header.miner, 20, "miner")?,
+        fixed_field(&header.state_root, 32, "state_root")?,
+        fixed_field(&header.transaction_root, 32, "transaction_root")?,
+        fixed_field(&header.receipts_root, 32, "receipts_root")?,
+        fixed_field(&header.logs_bloom, 256, "logs_bloom")?,
+        integer_field(&header.difficulty, "difficulty")?,
+        u64_bytes(header.number as u64),
+        u64_bytes(header.gas_limit as u64),
+        u64_bytes(header.gas_used as u64),
+        integer_field(&header.timestamp, "timestamp")?,
+        bytes(required(&header.extra_data, "extra_data")?, "extra_data")?,
+        fixed_field(&header.mix_hash, 32, "mix_hash")?,
+        fixed(&header.nonce, 8, "nonce")?,
+    ];
+
+    // Fork specific fields, in the order they were introduced. Once a field is
+    // missing, none of the later ones may be present.
+    let optional_fields: [(&'static str, Option<&str>, FieldKind); 6] = [
+        (
+            "base_fee_per_gas",
+            header.base_fee_per_gas.as_deref(),
+            FieldKind::Integer,
+        ),
+        (
+            "withdrawals_root",
+            header.withdrawals_root.as_deref(),
+            FieldKind::Hash,
+        ),
+        (
+            "blob_gas_used",
+            header.blob_gas_used.as_deref(),
+            FieldKind::Integer,
+        ),
+        (
+            "excess_blob_gas",
+            header.excess_blob_gas.as_deref(),
+            FieldKind::Integer,
+        ),
+        (
+            "parent_beacon_block_root",
+            header.parent_beacon_block_root.as_deref(),
+            FieldKind::Hash,
+        ),
+        ("requests_hash", requests_hash, FieldKind::Hash),
+    ];
+
+    let mut fork_ended = false;
+    for (name, value, kind) in optional_fields {
+        match value {
+            Some(_) if fork_ended => return Err(HeaderError::UnexpectedField(name)),
+            Some(value) => {
+                fields.push(match kind {
+                    FieldKind::Integer => integer(value, name)?,
+                    FieldKind::Hash => fixed(value, 32, name)?,
+                });
+            }
+            None => fork_ended = true,
+        }
+    }
+
+    let items: Vec<Vec<u8>> = fields.iter().map(|field| encode_bytes(field)).collect();
+    Ok(encode_list(&items))
+}
+
+enum FieldKind {
+    Integer,
+    Hash,
+}
+
+fn required<'a>(value: &'a Option<String>, name: &'static str) -> Result<&'a str, HeaderError> {
+    value.as_deref().ok_or(HeaderError::MissingField(name))
+}
+
+fn fixed_field(
+    value: &Option<String>,
+    size: usize,
+    name: &'static str,
+) -> Result<Vec<u8>, HeaderError> {
+    fixed(required(value, name)?, size, name)
+}
+
+fn integer_field(value: &Option<String>, name: &'static str) -> Result<Vec<u8>, HeaderError> {
+    integer(required(value, name)?, name)
+}
+
+/// Decodes a hex string, accepting an odd number of digits.
+fn bytes(value: &str, name: &'static str) -> Result<Vec<u8>, HeaderError> {
+    let digits = strip_hex_prefix(value);
+    let decoded = if digits.len() % 2 == 1 {
+        hex::decode(format!("0{}", digits))
+    } else {
+        hex::decode(digits)
+    };
+    decoded.map_err(|_| HeaderError::InvalidHex(name))
+}
+
+/// Decodes a fixed size field, left padding it with zeros.
+fn fixed(value: &str, size: usize, name: &'static str) -> Result<Vec<u8>, HeaderError> {
+    let mut decoded = bytes(value, name)?;
+    if decoded.len() > size {
+        return Err(HeaderError::FieldTooLong(name, size));
+    }
+    let mut padded = vec![0u8; size - decoded.len()];
+    padded.append(&mut decoded);
+    Ok(padded)
+}
+
+/// Decodes an integer field into its minimal big endian representation.
+fn integer(value: &str, name: &'static str) -> Result<Vec<u8>, HeaderError> {
+    let decoded = bytes(value, name)?;
+    let first_non_zero = decoded
+        .iter()
+        .position(|b| *b != 0)
+        .unwrap_or(decoded.len());
+    Ok(decoded[first_non_zero..].to_vec())
+}
+
+fn u64_bytes(value: u64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let first_non_zero = bytes.iter().position(|b| *b != 0).unwrap_or(bytes.len());
+    bytes[first_non_zero..].to_vec()
+}
+
+fn encode_length(len: usize, offset: u8) -> Vec<u8> {
+    if len <= 55 {
+        vec![offset + len as u8]
+    } else {
+        let len_bytes = u64_bytes(len as u64);
+        let mut prefix = vec![offset + 55 + len_bytes.len() as u8];
+        prefix.extend_from_slice(&len_bytes);
+        prefix
+    }
+}
+
+fn encode_bytes(value: &[u8]) -> Vec<u8> {
+    if value.len() == 1 && value[0] < 0x80 {
+        return value.to_vec();
+    }
+    let mut encoded = encode_length(value.len(), 0x80);
+    encoded.extend_from_slice(value);
+    encoded
+}
+
+fn encode_list(items: &[Vec<u8>]) -> Vec<u8> {
+    let payload_len = items.iter().map(|item| item.len()).sum();
+    let mut encoded = encode_length(payload_len, 0xc0);
+    for item in items {
+        encoded.extend_from_slice(item);
+    }
+    encoded
+}
@@ -0,0 +1,235 @@
+use crate::felt::{self, elements_count_to_felt};
+use crate::hasher::HasherKind;
+use serde::{Deserialize, Serialize};
+use starknet_crypto::Felt;
+use thiserror::Error;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Proof {
+    pub element_index: usize,
+    #[serde(with = "felt")]
+    pub element_hash: Felt,
+    #[serde(with = "felt::vec")]
+    pub siblings_hashes: Vec<Felt>,
+    #[serde(with = "felt::vec")]
+    pub peaks_hashes: Vec<Felt>,
+    pub elements_count: usize,
+    /// Hasher the MMR was built with
+    #[serde(default)]
+    pub hasher: HasherKind,
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum InclusionProofError {
+    #[error("Element index {0} is not a leaf of the MMR")]
+    InvalidElementIndex(usize),
+    #[error("Invalid elements count {0}")]
+    InvalidElementsCount(usize),
+    #[error("Expected {expected} peaks, got {actual}")]
+    InvalidPeaksCount { expected: usize, actual: usize },
+}
+
+/// Verifies that `proof.element_hash` is committed in the MMR with the given root.
+///
+/// The root is computed with `calculate_root_hash`: the peaks are bagged
+/// right to left with `proof.hasher` and hashed together with the elements
+/// count.
+pub fn verify_inclusion_proof(proof: &Proof, root: &Felt) -> Result<bool, InclusionProofError> {
+    let peaks = find_peaks(proof.elements_count);
+    if peaks.is_empty() {
+        return Err(InclusionProofError::InvalidElementsCount(
+            proof.elements_count,
+        ));
+    }
+    if peaks.len() != proof.peaks_hashes.len() {
+        return Err(InclusionProofError::InvalidPeaksCount {
+            expected: peaks.len(),
+            actual: proof.peaks_hashes.len(),
+        });
+    }
+    if proof.element_index == 0 || proof.element_index > proof.elements_count {
+        return Err(InclusionProofError::InvalidElementIndex(
+            proof.element_index,
+        ));
+    }
+
+    let mut leaf_index = element_index_to_leaf_index(proof.element_index)?;
+    let mut element_index = proof.element_index;
+    let mut hash = proof.element_hash;
+
+    for (height, sibling) in proof.siblings_hashes.iter().enumerate() {
+        let siblings_offset = (2 << height) - 1;
+        if leaf_index % 2 == 1 {
+            // Right child, the sibling is on the left
+            hash = proof.hasher.hash_pair(*sibling, hash);
+            element_index += 1;
+        } else {
+            hash = proof.hasher.hash_pair(hash, *sibling);
+            element_index += siblings_offset + 1;
+        }
+        leaf_index /= 2;
+    }
+
+    // The path must end at one of the peaks, with the matching hash
+    let peak_hash = match peaks.iter().position(|&peak| peak == element_index) {
+        Some(position) => &proof.peaks_hashes[position],
+        None => return Ok(false),
+    };
+    if hash != *peak_hash {
+        return Ok(false);
+    }
+
+    let computed_root =
+        calculate_root_hash(proof.hasher, &proof.peaks_hashes, proof.elements_count);
+    Ok(computed_root == *root)
+}
+
+/// Computes the MMR root from its peaks.
+pub fn calculate_root_hash(
+    hasher: HasherKind,
+    peaks_hashes: &[Felt],
+    elements_count: usize,
+) -> Felt {
+    root_from_bag(hasher, bag_peaks(hasher, peaks_hashes), elements_count)
+}
+
+/// Computes the MMR root from its bagged peaks, see `bag_peaks`.
+pub fn root_from_bag(hasher: HasherKind, bag: Felt, elements_count: usize) -> Felt {
+    hasher.hash_pair(elements_count_to_felt(elements_count), bag)
+}
+
+/// Element indices of the siblings on the path from `element_index` to its peak.
+pub fn find_siblings(
+    element_index: usize,
+    elements_count: usize,
+) -> Result<Vec<usize>, InclusionProofError> {
+    if element_index == 0 || element_index > elements_count {
+        return Err(InclusionProofError::InvalidElementIndex(element_index));
+    }
+
+    let mut leaf_index = element_index_to_leaf_index(element_index)?;
+    let mut height = 0;
+    let mut siblings = Vec::new();
+    let mut current_element_index = element_index;
+
+    while current_element_index <= elements_count {
+        let siblings_offset = (2 << height) - 1;
+        if leaf_index % 2 == 1 {
+            // Right child
+            siblings.push(current_element_index - siblings_offset);
+            current_element_index += 1;
+        } else {
+            // Left child
+            siblings.push(current_element_index + siblings_offset);
+            current_element_index += siblings_offset + 1;
+        }
+        leaf_index /= 2;
+        height += 1;
+    }
+
+    // The last one is past the peak
+    siblings.pop();
+    Ok(siblings)
+}
+
+/// Height of the node at `element_index`, leaves being at height 0.
+pub fn element_height(element_index: usize) -> usize {
+    let mut index = element_index;
+    while !all_ones(index) {
+        // Jump to the node at the same height in the left mountain
+        index -= (1 << (bit_length(index) - 1)) - 1;
+    }
+    bit_length(index) - 1
+}
+
+/// Element indices of the left and right children of the parent node at
+/// `element_index`, or `None` for a leaf.
+pub fn children_indices(element_index: usize) -> Option<(usize, usize)> {
+    match element_height(element_index) {
+        0 => None,
+        height => Some((element_index - (1 << height), element_index - 1)),
+    }
+}
+
+/// Hash of a parent node from its children, as done by `GuestMMR::append`.
+pub fn hash_children(hasher: HasherKind, left: &Felt, right: &Felt) -> Felt {
+    hasher.hash_pair(*left, *right)
+}
+
+/// Element indices of the peaks of an MMR of `elements_count` elements, or
+/// none if that is not a valid MMR size.
+pub fn find_peaks(mut elements_count: usize) -> Vec<usize> {
+    let mut mountain_elements_count = (1 << bit_length(elements_count)) - 1;
+    let mut mountain_index_shift = 0;
+    let mut peaks = Vec::new();
+
+    while mountain_elements_count > 0 {
+        if mountain_elements_count <= elements_count {
+            mountain_index_shift += mountain_elements_count;
+            peaks.push(mountain_index_shift);
+            elements_count -= mountain_elements_count;
+        }
+        mountain_elements_count >>= 1;
+    }
+
+    if elements_count > 0 {
+        return Vec::new();
+    }
+
+    peaks
+}
+
+pub fn element_index_to_leaf_index(element_index: usize) -> Result<usize, InclusionProofError> {
+    if element_index == 0 {
+        return Err(InclusionProofError::InvalidElementIndex(element_index));
+    }
+    elements_count_to_leaf_count(element_index - 1)
+        .map_err(|_| InclusionProofError::InvalidElementIndex(element_index))
+}
+
+pub fn elements_count_to_leaf_count(elements_count: usize) -> Result<usize, InclusionProofError> {
+    let mut leaf_count = 0;
+    let mut mountain_leaf_count = 1 << bit_length(elements_count);
+    let mut current_elements_count = elements_count;
+
+    while mountain_leaf_count > 0 {
+        let mountain_elements_count = 2 * mountain_leaf_count - 1;
+        if mountain_elements_count <= current_elements_count {
+            leaf_count += mountain_leaf_count;
+            current_elements_count -= mountain_elements_count;
+        }
+        mountain_leaf_count >>= 1;
+    }
+
+    if current_elements_count > 0 {
+        return Err(InclusionProofError::InvalidElementsCount(elements_count));
+    }
+
+    Ok(leaf_count)
+}
+
+fn bit_length(num: usize) -> usize {
+    (std::mem::size_of::<usize>() * 8) - num.leading_zeros() as usize
+}
+
+fn all_ones(num: usize) -> bool {
+    num != 0 && num.count_ones() as usize == bit_length(num)
+}
+
+/// Folds the peaks into a single hash, right to left.
+pub fn bag_peaks(hasher: HasherKind, peaks: &[Felt]) -> Felt {
+    match peaks.len() {
+        0 => Felt::ZERO,
+        1 => peaks[0],
+        _ => {
+            let last = peaks[peaks.len() - 1];
+            let second_last = peaks[peaks.len() - 2];
+            let root0 = hasher.hash_pair(second_last, last);
+
+            peaks[..peaks.len() - 2]
+                .iter()
+                .rev()
+                .fold(root0, |prev, cur| hasher.hash_pair(*cur, prev))
+        }
+    }
+}
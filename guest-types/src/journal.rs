@@ -0,0 +1,149 @@
+use crate::{felt, AppendMode, AppendResult, GuestOutput, HasherKind, MmrNode};
+use serde::{Deserialize, Serialize};
+use starknet_crypto::Felt;
+use thiserror::Error;
+
+/// What the batch guest commits to its journal.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JournalFormat {
+    /// The whole `GuestOutput`, including every append result and new node
+    #[default]
+    Full,
+    /// A `CompactGuestOutput`; the append results and new nodes are sent to
+    /// the host through stdout as a `BatchSideOutput`
+    Compact,
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum JournalError {
+    #[error("Side output does not match the committed digest")]
+    DigestMismatch,
+}
+
+/// Journal of a batch in the compact format: the start and end state of the
+/// MMR and a digest of everything the batch appended.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompactGuestOutput {
+    #[serde(with = "felt::vec")]
+    pub initial_peaks: Vec<Felt>,
+    pub initial_elements_count: usize,
+    pub initial_leaves_count: usize,
+    #[serde(with = "felt::vec")]
+    pub final_peaks: Vec<Felt>,
+    pub elements_count: usize,
+    pub leaves_count: usize,
+    /// `GuestOutput::mode`
+    pub mode: AppendMode,
+    /// First and last block number of the batch, `None` for generic leaves
+    pub block_range: Option<(u64, u64)>,
+    /// `GuestOutput::chain_block_range`
+    pub chain_block_range: Option<(u64, u64)>,
+    /// Hasher the MMR is built with
+    pub hasher: HasherKind,
+    /// `GuestInput::method_id`
+    pub method_id: [u32; 8],
+    /// `GuestInput::aggregation_method_id`
+    pub aggregation_method_id: [u32; 8],
+    /// `GuestOutput::chained`
+    pub chained: bool,
+    /// `append_digest` of the batch's append results and new nodes
+    #[serde(with = "felt")]
+    pub append_digest: Felt,
+}
+
+/// The part of a `GuestOutput` left out of a compact journal.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchSideOutput {
+    pub append_results: Vec<AppendResult>,
+    pub new_nodes: Vec<MmrNode>,
+}
+
+impl CompactGuestOutput {
+    /// Rebuild the full output from the side output sent by the guest,
+    /// checking it against the committed digest.
+    pub fn expand(self, side_output: BatchSideOutput) -> Result<GuestOutput, JournalError> {
+        let digest = append_digest(
+            self.hasher,
+            &side_output.append_results,
+            &side_output.new_nodes,
+        );
+        if digest != self.append_digest {
+            return Err(JournalError::DigestMismatch);
+        }
+
+        Ok(GuestOutput {
+            initial_peaks: self.initial_peaks,
+            initial_elements_count: self.initial_elements_count,
+            initial_leaves_count: self.initial_leaves_count,
+            final_peaks: self.final_peaks,
+            elements_count: self.elements_count,
+            leaves_count: self.leaves_count,
+            mode: self.mode,
+            block_range: self.block_range,
+            chain_block_range: self.chain_block_range,
+            hasher: self.hasher,
+            method_id: self.method_id,
+            aggregation_method_id: self.aggregation_method_id,
+            chained: self.chained,
+            append_results: side_output.append_results,
+            new_nodes: side_output.new_nodes,
+        })
+    }
+}
+
+impl GuestOutput {
+    /// Split the output into a compact journal and the side output it commits to.
+    pub fn into_compact(self) -> (CompactGuestOutput, BatchSideOutput) {
+        let append_digest = append_digest(self.hasher, &self.append_results, &self.new_nodes);
+
+        let journal = CompactGuestOutput {
+            initial_peaks: self.initial_peaks,
+            initial_elements_count: self.initial_elements_count,
+            initial_leaves_count: self.initial_leaves_count,
+            final_peaks: self.final_peaks,
+            elements_count: self.elements_count,
+            leaves_count: self.leaves_count,
+            mode: self.mode,
+            block_range: self.block_range,
+            chain_block_range: self.chain_block_range,
+            hasher: self.hasher,
+            method_id: self.method_id,
+            aggregation_method_id: self.aggregation_method_id,
+            chained: self.chained,
+            append_digest,
+        };
+        let side_output = BatchSideOutput {
+            append_results: self.append_results,
+            new_nodes: self.new_nodes,
+        };
+
+        (journal, side_output)
+    }
+}
+
+/// Hash, with the MMR's hasher, over the number of append results and of
+/// new nodes, followed by the append results and the new nodes. Each append
+/// result contributes its leaves count, elements count, element index and
+/// root hash, each node its index and hash. The counts make the split
+/// between results and nodes unambiguous.
+pub fn append_digest(
+    hasher: HasherKind,
+    append_results: &[AppendResult],
+    new_nodes: &[MmrNode],
+) -> Felt {
+    let mut felts = Vec::with_capacity(2 + append_results.len() * 4 + new_nodes.len() * 2);
+    felts.push(Felt::from(append_results.len() as u64));
+    felts.push(Felt::from(new_nodes.len() as u64));
+    for result in append_results {
+        felts.push(Felt::from(result.leaves_count as u64));
+        felts.push(Felt::from(result.elements_count as u64));
+        felts.push(Felt::from(result.element_index as u64));
+        felts.push(result.root_hash);
+    }
+    for node in new_nodes {
+        felts.push(Felt::from(node.index as u64));
+        felts.push(node.hash);
+    }
+
+    hasher.hash_many(&felts)
+}
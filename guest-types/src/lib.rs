@@ -1,6 +1,20 @@
 use block_validity::BlockHeader;
-use risc0_zkvm::Receipt;
 use serde::{Deserialize, Serialize};
+pub use starknet_crypto::Felt;
+
+pub mod aggregation;
+pub mod felt;
+pub mod hasher;
+pub mod header_rlp;
+pub mod inclusion;
+pub mod journal;
+pub use aggregation::{
+    chain_transitions, merge_block_ranges, AggregationError, AggregationInput, AggregationOutput,
+    BatchTransition,
+};
+pub use hasher::{HasherKind, KeccakHasher, MmrHasher, PoseidonHasher, Sha256Hasher};
+pub use inclusion::{calculate_root_hash, verify_inclusion_proof, InclusionProofError, Proof};
+pub use journal::{BatchSideOutput, CompactGuestOutput, JournalError, JournalFormat};
 
 #[derive(Clone, Default)]
 pub struct PeaksOptions {
@@ -11,45 +25,174 @@ pub struct PeaksOptions {
 #[derive(Clone)]
 pub struct FormattingOptions {
     pub output_size: usize,
-    pub null_value: String,
+    pub null_value: Felt,
 }
 
 pub type PeaksFormattingOptions = FormattingOptions;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppendResult {
     pub leaves_count: usize,
     pub elements_count: usize,
     pub element_index: usize,
-    pub root_hash: String,
+    #[serde(with = "felt")]
+    pub root_hash: Felt,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MmrNode {
+    pub index: usize,
+    #[serde(with = "felt")]
+    pub hash: Felt,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GuestOutput {
-    pub final_peaks: Vec<String>,
+    #[serde(with = "felt::vec")]
+    pub initial_peaks: Vec<Felt>,
+    pub initial_elements_count: usize,
+    pub initial_leaves_count: usize,
+    #[serde(with = "felt::vec")]
+    pub final_peaks: Vec<Felt>,
     pub elements_count: usize,
     pub leaves_count: usize,
+    /// Whether the batch appended block hashes or generic leaves. An MMR
+    /// holds one or the other, never both.
+    pub mode: AppendMode,
+    /// First and last block number of the batch, `None` for generic leaves
+    pub block_range: Option<(u64, u64)>,
+    /// Lowest and highest block number of this batch and of every batch it
+    /// is chained to, which a later batch must not append again. `None` for
+    /// generic leaves.
+    pub chain_block_range: Option<(u64, u64)>,
+    /// Hasher the MMR is built with
+    pub hasher: HasherKind,
+    /// `GuestInput::method_id`
+    pub method_id: [u32; 8],
+    /// `GuestInput::aggregation_method_id`
+    pub aggregation_method_id: [u32; 8],
+    /// True if every batch since the empty MMR is proven, i.e. this batch
+    /// starts from the empty MMR or continues a chained proof. Receipts
+    /// that are not chained only attest to the batch itself.
+    pub chained: bool,
     pub append_results: Vec<AppendResult>,
+    /// Every node inserted into the MMR by this batch, leaves and parents
+    pub new_nodes: Vec<MmrNode>,
+}
+
+/// File descriptor on which the batch guest writes its `PhaseCycles`.
+pub const PHASE_CYCLES_FD: u32 = 10;
+
+/// Cycles spent by the batch guest in each of its phases, measured with
+/// `env::cycle_count()`. Diagnostics only, not part of the journal.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PhaseCycles {
+    pub previous_proof_verification: u64,
+    pub header_validation: u64,
+    pub mmr_appends: u64,
+    pub peak_extraction: u64,
+}
+
+/// How the guest interprets `GuestInput::new_elements`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AppendMode {
+    /// Each element is the hash of the header at the same position in `headers`.
+    BlockHashes,
+    /// Elements are arbitrary felts (state roots, receipts roots, ...) and no
+    /// headers are provided.
+    GenericLeaves,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct CombinedInput {
+    pub mode: AppendMode,
     pub headers: Vec<BlockHeader>,
+    /// Prague `requests_hash` of each header, which `BlockHeader` does not carry.
+    /// Missing entries are treated as pre-Prague headers.
+    #[serde(default)]
+    pub requests_hashes: Vec<Option<String>>,
+    /// Format of the journal committed by this batch
+    #[serde(default)]
+    pub journal_format: JournalFormat,
+    /// Hasher the MMR is built with, which must match the previous batches
+    #[serde(default)]
+    pub hasher: HasherKind,
     pub mmr_input: GuestInput,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GuestInput {
-    pub initial_peaks: Vec<String>,
+    #[serde(with = "felt::vec")]
+    pub initial_peaks: Vec<Felt>,
     pub elements_count: usize,
     pub leaves_count: usize,
-    pub new_elements: Vec<String>,
-    pub previous_proofs: Vec<BatchProof>,
+    /// Elements appended to the MMR, in order
+    #[serde(with = "felt::vec")]
+    pub new_elements: Vec<Felt>,
+    /// Proof of the immediately preceding batch, which attests to every batch before it
+    pub previous_proofs: Option<BatchProof>,
+    /// Image ID of the batch guest. The guest cannot know its own ID, so it
+    /// commits this one and requires it of the previous proof; verifiers
+    /// must check it against the ID the receipt was verified with.
+    pub method_id: [u32; 8],
+    /// Image ID of the aggregation guest, required of a previous proof that
+    /// aggregates batches and committed like `method_id`.
+    pub aggregation_method_id: [u32; 8],
+}
+
+impl GuestInput {
+    /// Returns true if the batch starts from the empty MMR, the only state
+    /// whose history needs no proof.
+    pub fn starts_empty(&self) -> bool {
+        self.elements_count == 0 && self.leaves_count == 0 && self.initial_peaks.is_empty()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BatchProof {
-    pub receipt: Receipt,
-    pub image_id: Vec<u8>,
+    /// Journal of the previous batch; its receipt is supplied to the prover as an assumption
+    pub journal: Vec<u8>,
     pub method_id: [u32; 8],
+    /// Format of `journal`, for batch proofs
+    #[serde(default)]
+    pub format: JournalFormat,
+    /// Guest that committed `journal`
+    #[serde(default)]
+    pub kind: ProofKind,
+}
+
+/// Guest a `BatchProof` comes from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ProofKind {
+    /// The batch guest, committing a `GuestOutput` or a `CompactGuestOutput`
+    #[default]
+    Batch,
+    /// The aggregation guest, committing an `AggregationOutput`
+    Aggregation,
+}
+
+/// Input of the inclusion guest, proving that `header` is committed in the MMR
+/// with the given `root`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InclusionInput {
+    pub header: BlockHeader,
+    /// Prague `requests_hash` of the header, which `BlockHeader` does not carry
+    pub requests_hash: Option<String>,
+    pub proof: Proof,
+    #[serde(with = "felt")]
+    pub root: Felt,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InclusionOutput {
+    #[serde(with = "felt")]
+    pub root: Felt,
+    pub elements_count: usize,
+    pub block_number: u64,
+    pub block_hash: String,
+    pub parent_hash: Option<String>,
+    pub state_root: Option<String>,
+    pub transaction_root: Option<String>,
+    pub receipts_root: Option<String>,
+    pub timestamp: Option<String>,
 }
@@ -1,289 +1,32 @@
-use guest_types::{AppendResult, PeaksFormattingOptions, PeaksOptions};
-use serde::{Deserialize, Serialize};
-use starknet_crypto::{poseidon_hash, poseidon_hash_many, poseidon_hash_single, Felt};
-use std::collections::{HashMap, VecDeque};
-use thiserror::Error;
-
-#[derive(Error, Debug)]
-pub enum FormattingError {
-    #[error("Formatting: Expected peaks output size is smaller than the actual size")]
-    PeaksOutputSizeError,
-}
-
-#[derive(Error, Debug)]
-pub enum MMRError {
-    NoHashFoundForIndex(usize),
-    Formatting(FormattingError),
-    InsufficientPeaksForMerge,
-    HashError,
-}
-
-pub struct GuestMMR {
-    hashes: HashMap<usize, String>,
-    elements_count: usize,
-    leaves_count: usize,
-}
-
-impl GuestMMR {
-    pub fn new(initial_peaks: Vec<String>, elements_count: usize, leaves_count: usize) -> Self {
-        let mut hashes = HashMap::new();
-
-        // Initialize hashes with the peaks at their correct positions
-        let peak_positions = find_peaks(elements_count);
-        for (peak, pos) in initial_peaks.into_iter().zip(peak_positions) {
-            hashes.insert(pos, peak);
-        }
-
-        Self {
-            elements_count,
-            leaves_count,
-            hashes,
-        }
-    }
-
-    pub fn get_elements_count(&self) -> usize {
-        self.elements_count
-    }
-
-    pub fn get_leaves_count(&self) -> usize {
-        self.leaves_count
-    }
-
-    pub fn append(&mut self, value: String) -> Result<AppendResult, MMRError> {
-        let elements_count = self.elements_count;
-        println!("elements_count: {}", elements_count);
-
-        let mut peaks: Vec<String> = self.retrieve_peaks_hashes(find_peaks(elements_count))?;
-        println!("peaks: {:?}", peaks);
-
-        let mut last_element_idx = self.elements_count + 1;
-        let leaf_element_index = last_element_idx;
-        println!("leaf_element_index: {}", leaf_element_index);
-
-        // Store the new leaf in the hash map
-        self.hashes.insert(last_element_idx, value.clone());
-
-        peaks.push(value);
-
-        let no_merges = leaf_count_to_append_no_merges(self.leaves_count);
-        println!("no_merges: {}", no_merges);
-        for _ in 0..no_merges {
-            if peaks.len() < 2 {
-                return Err(MMRError::InsufficientPeaksForMerge);
-            }
-
-            last_element_idx += 1;
-
-            // Pop the last two peaks to merge
-            let right_hash = peaks.pop().unwrap();
-            println!("right_hash: {}", right_hash);
-            let left_hash = peaks.pop().unwrap();
-            println!("left_hash: {}", left_hash);
-
-            let parent_hash = hash(vec![left_hash, right_hash])?;
-            println!("parent_hash: {}", parent_hash);
-
-            self.hashes.insert(last_element_idx, parent_hash.clone());
-
-            peaks.push(parent_hash);
-        }
-
-        self.elements_count = last_element_idx;
-        self.leaves_count += 1;
-
-        let bag = self.bag_the_peaks()?;
-        println!("bag: {}", bag);
-
-        let root_hash = self.calculate_root_hash(&bag, last_element_idx)?;
-        println!("last_element_idx: {}", last_element_idx);
-        println!("root_hash: {}", root_hash);
-
-        Ok(AppendResult {
-            leaves_count: self.leaves_count,
-            elements_count: last_element_idx,
-            element_index: leaf_element_index,
-            root_hash,
-        })
-    }
-
-    fn retrieve_peaks_hashes(&self, peak_idxs: Vec<usize>) -> Result<Vec<String>, MMRError> {
-        let mut peaks = Vec::new();
-
-        for &idx in &peak_idxs {
-            // Use `idx` directly since `self.hashes` expects a `usize` key
-            if let Some(hash) = self.hashes.get(&idx) {
-                peaks.push(hash.clone());
-            } else {
-                return Err(MMRError::NoHashFoundForIndex(idx));
-            }
-        }
-
-        Ok(peaks)
-    }
-
-    fn bag_the_peaks(&self) -> Result<String, MMRError> {
-        let peaks_idxs = find_peaks(self.elements_count);
-
-        let peaks_hashes = self.retrieve_peaks_hashes(peaks_idxs)?;
-
-        match peaks_hashes.len() {
-            0 => Ok("0x0".to_string()),
-            1 => Ok(peaks_hashes[0].clone()),
-            _ => {
-                let mut peaks_hashes: VecDeque<String> = peaks_hashes.into();
-                let last = peaks_hashes.pop_back().unwrap();
-                let second_last = peaks_hashes.pop_back().unwrap();
-                let root0 = hash(vec![second_last, last])?;
-
-                let final_root = peaks_hashes
-                    .into_iter()
-                    .rev()
-                    .fold(root0, |prev: String, cur: String| {
-                        hash(vec![cur, prev]).unwrap()
-                    });
-
-                Ok(final_root)
-            }
-        }
-    }
-
-    pub fn calculate_root_hash(
-        &self,
-        bag: &str,
-        elements_count: usize,
-    ) -> Result<String, MMRError> {
-        match hash(vec![elements_count.to_string(), bag.to_string()]) {
-            Ok(root_hash) => Ok(root_hash),
-            Err(_) => Err(MMRError::HashError),
-        }
-    }
-
-    pub fn get_peaks(&self, option: PeaksOptions) -> Result<Vec<String>, MMRError> {
-        let tree_size = match option.elements_count {
-            Some(count) => count,
-            None => self.elements_count,
-        };
-
-        let peaks_indices = find_peaks(tree_size);
-        let peaks = self.retrieve_peaks_hashes(peaks_indices)?;
-
-        if let Some(formatting_opts) = option.formatting_opts {
-            match format_peaks(peaks, &formatting_opts) {
-                Ok(formatted_peaks) => Ok(formatted_peaks),
-                Err(e) => Err(MMRError::Formatting(e)),
-            }
-        } else {
-            Ok(peaks)
-        }
-    }
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Proof {
-    pub element_index: usize,
-    pub element_hash: String,
-    pub siblings_hashes: Vec<String>,
-    pub peaks_hashes: Vec<String>,
-    pub elements_count: usize,
-}
-
-impl std::fmt::Display for MMRError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            MMRError::NoHashFoundForIndex(idx) => write!(f, "No hash found for index {}", idx),
-            MMRError::Formatting(e) => write!(f, "Formatting error: {}", e),
-            MMRError::InsufficientPeaksForMerge => write!(f, "Insufficient peaks for merge"),
-            MMRError::HashError => write!(f, "Hash error"),
-        }
-    }
-}
-
-pub fn format_peaks(
-    mut peaks: Vec<String>,
-    formatting_opts: &PeaksFormattingOptions,
-) -> Result<Vec<String>, FormattingError> {
-    if peaks.len() > formatting_opts.output_size {
-        return Err(FormattingError::PeaksOutputSizeError);
-    }
-
-    let expected_peaks_size_remainder = formatting_opts.output_size - peaks.len();
-    let peaks_null_values: Vec<String> =
-        vec![formatting_opts.null_value.clone(); expected_peaks_size_remainder];
-
-    peaks.extend(peaks_null_values);
-
-    Ok(peaks)
-}
-
-// Add this function at the bottom with other helper functions
-pub fn find_peaks(mut elements_count: usize) -> Vec<usize> {
-    let mut mountain_elements_count = (1 << bit_length(elements_count)) - 1;
-    let mut mountain_index_shift = 0;
-    let mut peaks = Vec::new();
-
-    while mountain_elements_count > 0 {
-        if mountain_elements_count <= elements_count {
-            mountain_index_shift += mountain_elements_count;
-            peaks.push(mountain_index_shift);
-            elements_count -= mountain_elements_count;
-        }
-        mountain_elements_count >>= 1;
-    }
-
-    if elements_count > 0 {
-        return Vec::new();
-    }
-
-    peaks
-}
-
-fn bit_length(num: usize) -> usize {
-    (std::mem::size_of::<usize>() * 8) - num.leading_zeros() as usize
-}
-
-fn leaf_count_to_append_no_merges(leaf_count: usize) -> usize {
-    if leaf_count == 0 {
-        return 0;
-    }
-    (!leaf_count).trailing_zeros() as usize
-}
-
-fn hash(data: Vec<String>) -> Result<String, MMRError> {
-    // for element in &data {
-    //     self.is_element_size_valid(element)?;
-    // }
-
-    let field_elements: Vec<Felt> = data
-        .iter()
-        .map(|e| Felt::from_hex(e).unwrap_or_default())
-        .collect();
-
-    let hash_core = match field_elements.len() {
-        0 => return Err(MMRError::HashError),
-        1 => poseidon_hash_single(field_elements[0]),
-        2 => poseidon_hash(field_elements[0], field_elements[1]),
-        _ => poseidon_hash_many(&field_elements),
-    };
-
-    let hash = format!("{:x}", hash_core);
-    // if self.should_pad {
-    //     hash = format!("{:0>63}", hash);
-    // }
-    let hash = format!("0x{}", hash);
-    Ok(hash)
-}
-
 #[cfg(test)]
 mod tests {
-    use super::*;
-    use guest_types::{PeaksFormattingOptions, PeaksOptions};
+    use guest_mmr::{
+        append_batch, block_hash_element, find_peaks, format_peaks, parse_element, FormattingError,
+        GuestMMR, MMRError,
+    };
+    use guest_types::header_rlp::{block_hashes_match, compute_block_hash};
+    use guest_types::inclusion::{children_indices, find_siblings, hash_children};
+    use guest_types::journal::append_digest;
+    use guest_types::{
+        chain_transitions, merge_block_ranges, verify_inclusion_proof, AggregationError,
+        AppendMode, AppendResult, BatchSideOutput, BatchTransition, GuestInput, GuestOutput,
+        HasherKind, JournalError, KeccakHasher, MmrHasher, MmrNode, PeaksFormattingOptions,
+        PeaksOptions, PoseidonHasher, Proof, Sha256Hasher,
+    };
+    use serde_json::json;
+    use starknet_crypto::{poseidon_hash, Felt};
+
+    fn felt(hex: &str) -> Felt {
+        Felt::from_hex(hex).expect("Invalid felt")
+    }
 
     #[test]
     fn test_guest_mmr_initialization() {
-        let initial_peaks = vec!["0xabc".to_string(), "0xdef".to_string()];
-        let elements_count = 3;
-        let leaves_count = 2;
-        let guest_mmr = GuestMMR::new(initial_peaks.clone(), elements_count, leaves_count);
+        let initial_peaks = vec![felt("0xabc"), felt("0xdef")];
+        let elements_count = 4;
+        let leaves_count = 3;
+        let guest_mmr = GuestMMR::new(initial_peaks.clone(), elements_count, leaves_count)
+            .expect("Invalid initial state");
 
         // Check elements and leaves count
         assert_eq!(guest_mmr.get_elements_count(), elements_count);
@@ -292,8 +35,33 @@ mod tests {
         // Check initial peaks are stored correctly
         let peak_positions = find_peaks(elements_count);
         for (peak, pos) in initial_peaks.iter().zip(peak_positions) {
-            assert_eq!(guest_mmr.hashes.get(&pos).unwrap(), peak);
+            assert_eq!(guest_mmr.get_hash(pos), Some(*peak));
+        }
+    }
+
+    #[test]
+    fn test_guest_mmr_rejects_invalid_initial_state() {
+        let peaks = vec![felt("0xabc"), felt("0xdef")];
+        let invalid_states = [
+            // Peaks missing, or one too many
+            (vec![felt("0xabc")], 4, 3),
+            (vec![felt("0xabc"), felt("0xdef"), felt("0x123")], 4, 3),
+            // Not a valid MMR size
+            (vec![], 2, 1),
+            (peaks.clone(), 5, 3),
+            // Leaves count not matching the size
+            (peaks.clone(), 4, 4),
+            (vec![], 0, 4),
+            (vec![felt("0xabc")], 0, 0),
+        ];
+
+        for (initial_peaks, elements_count, leaves_count) in invalid_states {
+            assert!(matches!(
+                GuestMMR::new(initial_peaks, elements_count, leaves_count),
+                Err(MMRError::InvalidInitialState { .. })
+            ));
         }
+        assert!(GuestMMR::new(peaks, 4, 3).is_ok());
     }
 
     #[test]
@@ -302,17 +70,18 @@ mod tests {
         let initial_peaks = vec![];
         let elements_count = 0;
         let leaves_count = 0;
-        let mut guest_mmr = GuestMMR::new(initial_peaks, elements_count, leaves_count);
+        let mut guest_mmr = GuestMMR::new(initial_peaks, elements_count, leaves_count)
+            .expect("Invalid initial state");
 
         // Append a value
-        let value = "0x123".to_string();
-        let append_result = guest_mmr.append(value.clone()).expect("Append failed");
+        let value = felt("0x123");
+        let append_result = guest_mmr.append(value).expect("Append failed");
         // Check counts
         assert_eq!(guest_mmr.get_elements_count(), 1);
         assert_eq!(guest_mmr.get_leaves_count(), 1);
 
         // Check the new element is stored
-        assert_eq!(guest_mmr.hashes.get(&1).unwrap(), &value);
+        assert_eq!(guest_mmr.get_hash(1), Some(value));
 
         // Verify append result
         assert_eq!(append_result.leaves_count, 1);
@@ -333,19 +102,28 @@ mod tests {
         let initial_peaks = vec![];
         let elements_count = 0;
         let leaves_count = 0;
-        let mut guest_mmr = GuestMMR::new(initial_peaks, elements_count, leaves_count);
+        let mut guest_mmr = GuestMMR::new(initial_peaks, elements_count, leaves_count)
+            .expect("Invalid initial state");
 
         guest_mmr
-            .append("0x6c17009d66e34c1d6b7e4d73fd5a105243feb10c7cae9598d60b0fa97d08868".to_string())
+            .append(felt(
+                "0x6c17009d66e34c1d6b7e4d73fd5a105243feb10c7cae9598d60b0fa97d08868",
+            ))
             .expect("Append failed");
         guest_mmr
-            .append("0x4998b07fef69c1b1658fcb44d44fa5bb0ca62c835b26fe763ca14b61a6595da".to_string())
+            .append(felt(
+                "0x4998b07fef69c1b1658fcb44d44fa5bb0ca62c835b26fe763ca14b61a6595da",
+            ))
             .expect("Append failed");
         guest_mmr
-            .append("0x7337cf1262bf9eeaecffe02776fa1cc9fd35c6fc49303a2b5f39d96a7b46afa".to_string())
+            .append(felt(
+                "0x7337cf1262bf9eeaecffe02776fa1cc9fd35c6fc49303a2b5f39d96a7b46afa",
+            ))
             .expect("Append failed");
         guest_mmr
-            .append("0x16fa2f065f204a16db293c9adf370da4e08eea45874692dfa00123b21bbfe81".to_string())
+            .append(felt(
+                "0x16fa2f065f204a16db293c9adf370da4e08eea45874692dfa00123b21bbfe81",
+            ))
             .expect("Append failed");
         // Get peaks
         let peaks_options = PeaksOptions {
@@ -359,9 +137,10 @@ mod tests {
 
         // Expected peaks
         let peaks_indices = find_peaks(guest_mmr.get_elements_count());
-        let expected_peaks = guest_mmr
-            .retrieve_peaks_hashes(peaks_indices)
-            .expect("Retrieve peaks hashes failed");
+        let expected_peaks: Vec<Felt> = peaks_indices
+            .iter()
+            .map(|&idx| guest_mmr.get_hash(idx).expect("Missing peak"))
+            .collect();
 
         assert_eq!(peaks, expected_peaks);
     }
@@ -372,19 +151,28 @@ mod tests {
         let initial_peaks = vec![];
         let elements_count = 0;
         let leaves_count = 0;
-        let mut guest_mmr = GuestMMR::new(initial_peaks, elements_count, leaves_count);
+        let mut guest_mmr = GuestMMR::new(initial_peaks, elements_count, leaves_count)
+            .expect("Invalid initial state");
 
         guest_mmr
-            .append("0x6c17009d66e34c1d6b7e4d73fd5a105243feb10c7cae9598d60b0fa97d08868".to_string())
+            .append(felt(
+                "0x6c17009d66e34c1d6b7e4d73fd5a105243feb10c7cae9598d60b0fa97d08868",
+            ))
             .expect("Append failed");
         guest_mmr
-            .append("0x4998b07fef69c1b1658fcb44d44fa5bb0ca62c835b26fe763ca14b61a6595da".to_string())
+            .append(felt(
+                "0x4998b07fef69c1b1658fcb44d44fa5bb0ca62c835b26fe763ca14b61a6595da",
+            ))
             .expect("Append failed");
         guest_mmr
-            .append("0x7337cf1262bf9eeaecffe02776fa1cc9fd35c6fc49303a2b5f39d96a7b46afa".to_string())
+            .append(felt(
+                "0x7337cf1262bf9eeaecffe02776fa1cc9fd35c6fc49303a2b5f39d96a7b46afa",
+            ))
             .expect("Append failed");
         guest_mmr
-            .append("0x16fa2f065f204a16db293c9adf370da4e08eea45874692dfa00123b21bbfe81".to_string())
+            .append(felt(
+                "0x16fa2f065f204a16db293c9adf370da4e08eea45874692dfa00123b21bbfe81",
+            ))
             .expect("Append failed");
 
         // Bag the peaks
@@ -396,41 +184,1055 @@ mod tests {
             .calculate_root_hash(&bag, guest_mmr.get_elements_count())
             .expect("Calculate root hash failed");
         println!("root_hash: {:?}", root_hash);
-        // Verify root hash is not empty
-        assert!(!root_hash.is_empty());
+        // Verify root hash is not zero
+        assert_ne!(root_hash, Felt::ZERO);
     }
 
     #[test]
     fn test_format_peaks() {
-        let peaks = vec!["0x1".to_string(), "0x2".to_string()];
+        let peaks = vec![felt("0x1"), felt("0x2")];
         let formatting_opts = PeaksFormattingOptions {
             output_size: 4,
-            null_value: "0x0".to_string(),
+            null_value: Felt::ZERO,
         };
 
         let formatted_peaks =
             format_peaks(peaks.clone(), &formatting_opts).expect("Format peaks failed");
 
-        let expected_peaks = vec![
-            "0x1".to_string(),
-            "0x2".to_string(),
-            "0x0".to_string(),
-            "0x0".to_string(),
-        ];
+        let expected_peaks = vec![felt("0x1"), felt("0x2"), Felt::ZERO, Felt::ZERO];
 
         assert_eq!(formatted_peaks, expected_peaks);
     }
 
     #[test]
     fn test_format_peaks_error() {
-        let peaks = vec!["0x1".to_string(), "0x2".to_string(), "0x3".to_string()];
+        let peaks = vec![felt("0x1"), felt("0x2"), felt("0x3")];
         let formatting_opts = PeaksFormattingOptions {
             output_size: 2,
-            null_value: "0x0".to_string(),
+            null_value: Felt::ZERO,
         };
 
         let result = format_peaks(peaks, &formatting_opts);
 
         assert!(matches!(result, Err(FormattingError::PeaksOutputSizeError)));
     }
+
+    fn build_inclusion_proof<H: MmrHasher>(
+        guest_mmr: &GuestMMR<H>,
+        element_index: usize,
+        leaf: &str,
+    ) -> Proof {
+        let elements_count = guest_mmr.get_elements_count();
+        let siblings_hashes = find_siblings(element_index, elements_count)
+            .expect("Find siblings failed")
+            .iter()
+            .map(|&idx| guest_mmr.get_hash(idx).unwrap())
+            .collect();
+        let peaks_hashes = guest_mmr
+            .get_peaks(PeaksOptions::default())
+            .expect("Get peaks failed");
+
+        Proof {
+            element_index,
+            element_hash: felt(leaf),
+            siblings_hashes,
+            peaks_hashes,
+            elements_count,
+            hasher: H::KIND,
+        }
+    }
+
+    #[test]
+    fn test_verify_inclusion_proof() {
+        let mut guest_mmr = GuestMMR::new(vec![], 0, 0).expect("Invalid initial state");
+        let leaves = ["0x1", "0x2", "0x3", "0x4", "0x5", "0x6", "0x7"];
+
+        let mut element_indices = Vec::new();
+        for leaf in leaves {
+            let result = guest_mmr.append(felt(leaf)).expect("Append failed");
+            element_indices.push(result.element_index);
+        }
+
+        let bag = guest_mmr.bag_the_peaks().expect("Bag the peaks failed");
+        let root_hash = guest_mmr
+            .calculate_root_hash(&bag, guest_mmr.get_elements_count())
+            .expect("Calculate root hash failed");
+
+        for (leaf, element_index) in leaves.iter().zip(element_indices) {
+            let proof = build_inclusion_proof(&guest_mmr, element_index, leaf);
+            assert!(verify_inclusion_proof(&proof, &root_hash).expect("Verification failed"));
+        }
+    }
+
+    #[test]
+    fn test_verify_inclusion_proof_rejects_wrong_element() {
+        let mut guest_mmr = GuestMMR::new(vec![], 0, 0).expect("Invalid initial state");
+        for leaf in ["0x1", "0x2", "0x3", "0x4", "0x5"] {
+            guest_mmr.append(felt(leaf)).expect("Append failed");
+        }
+
+        let bag = guest_mmr.bag_the_peaks().expect("Bag the peaks failed");
+        let root_hash = guest_mmr
+            .calculate_root_hash(&bag, guest_mmr.get_elements_count())
+            .expect("Calculate root hash failed");
+
+        // Element 2 is the second leaf, "0x2"
+        let proof = build_inclusion_proof(&guest_mmr, 2, "0x9");
+        assert!(!verify_inclusion_proof(&proof, &root_hash).expect("Verification failed"));
+
+        let proof = build_inclusion_proof(&guest_mmr, 2, "0x2");
+        assert!(!verify_inclusion_proof(&proof, &felt("0x1234")).expect("Verification failed"));
+    }
+
+    #[test]
+    fn test_new_nodes_cover_appended_elements() {
+        let mut guest_mmr = GuestMMR::new(vec![], 0, 0).expect("Invalid initial state");
+        guest_mmr.append(felt("0x1")).expect("Append failed");
+        guest_mmr.append(felt("0x2")).expect("Append failed");
+
+        // A resumed MMR only reports the nodes it created itself
+        let peaks = guest_mmr
+            .get_peaks(PeaksOptions::default())
+            .expect("Get peaks failed");
+        let mut guest_mmr = GuestMMR::new(peaks, 3, 2).expect("Invalid initial state");
+        for leaf in ["0x3", "0x4", "0x5"] {
+            guest_mmr.append(felt(leaf)).expect("Append failed");
+        }
+
+        let new_nodes = guest_mmr.get_new_nodes();
+        let indices: Vec<usize> = new_nodes.iter().map(|node| node.index).collect();
+        assert_eq!(indices, (4..=8).collect::<Vec<_>>());
+
+        // Parents whose children were both created in this run hash correctly
+        let hash_at = |index: usize| {
+            new_nodes
+                .iter()
+                .find(|node| node.index == index)
+                .map(|node| node.hash)
+        };
+        assert_eq!(children_indices(5), None);
+        assert_eq!(children_indices(6), Some((4, 5)));
+        assert_eq!(children_indices(7), Some((3, 6)));
+        assert_eq!(
+            hash_at(6).unwrap(),
+            hash_children(
+                HasherKind::Poseidon,
+                &hash_at(4).unwrap(),
+                &hash_at(5).unwrap()
+            )
+        );
+        assert_eq!(hash_at(8), Some(felt("0x5")));
+    }
+
+    #[test]
+    fn test_compact_journal_roundtrip() {
+        let mut guest_mmr = GuestMMR::new(vec![], 0, 0).expect("Invalid initial state");
+        let append_results = ["0x1", "0x2", "0x3"]
+            .iter()
+            .map(|leaf| guest_mmr.append(felt(leaf)).expect("Append failed"))
+            .collect();
+        let final_peaks = guest_mmr
+            .get_peaks(PeaksOptions::default())
+            .expect("Get peaks failed");
+
+        let output = GuestOutput {
+            initial_peaks: vec![],
+            initial_elements_count: 0,
+            initial_leaves_count: 0,
+            final_peaks: final_peaks.clone(),
+            elements_count: guest_mmr.get_elements_count(),
+            leaves_count: guest_mmr.get_leaves_count(),
+            mode: AppendMode::GenericLeaves,
+            block_range: None,
+            chain_block_range: None,
+            hasher: HasherKind::Poseidon,
+            method_id: [0; 8],
+            aggregation_method_id: [0; 8],
+            chained: true,
+            append_results,
+            new_nodes: guest_mmr.get_new_nodes().to_vec(),
+        };
+        let (journal, side_output) = output.into_compact();
+        assert!(BatchTransition::from(journal.clone()).ends_at(&final_peaks, 4, 3));
+
+        // Tampering with a node breaks the digest
+        let mut tampered = BatchSideOutput {
+            append_results: vec![],
+            new_nodes: side_output.new_nodes.clone(),
+        };
+        tampered.new_nodes[0].hash = felt("0x9");
+        assert_eq!(
+            journal.clone().expand(tampered).unwrap_err(),
+            JournalError::DigestMismatch
+        );
+
+        let expanded = journal.expand(side_output).expect("Expand failed");
+        assert_eq!(expanded.new_nodes, guest_mmr.get_new_nodes());
+        assert_eq!(expanded.append_results.len(), 3);
+        assert_eq!(expanded.append_results[2].element_index, 4);
+    }
+
+    #[test]
+    fn test_append_digest_separates_results_from_nodes() {
+        // One append result is hashed as the same felts as these two nodes
+        let result = AppendResult {
+            leaves_count: 1,
+            elements_count: 2,
+            element_index: 3,
+            root_hash: felt("0x4"),
+        };
+        let nodes = [
+            MmrNode {
+                index: 1,
+                hash: felt("0x2"),
+            },
+            MmrNode {
+                index: 3,
+                hash: felt("0x4"),
+            },
+        ];
+
+        assert_ne!(
+            append_digest(HasherKind::Poseidon, &[result], &[]),
+            append_digest(HasherKind::Poseidon, &[], &nodes)
+        );
+    }
+
+    #[test]
+    fn test_chain_transitions() {
+        // Two batches of a backward build, blocks 11 to 20 then 1 to 10
+        let first = append_batch(
+            HasherKind::Poseidon,
+            GuestInput {
+                initial_peaks: vec![],
+                elements_count: 0,
+                leaves_count: 0,
+                new_elements: (11..=20u64).rev().map(Felt::from).collect(),
+                previous_proofs: None,
+                method_id: [0; 8],
+                aggregation_method_id: [0; 8],
+            },
+            Some((11, 20)),
+        )
+        .expect("Append failed");
+        let second = append_batch(
+            HasherKind::Poseidon,
+            GuestInput {
+                initial_peaks: first.final_peaks.clone(),
+                elements_count: first.elements_count,
+                leaves_count: first.leaves_count,
+                new_elements: (1..=10u64).rev().map(Felt::from).collect(),
+                previous_proofs: None,
+                method_id: [0; 8],
+                aggregation_method_id: [0; 8],
+            },
+            Some((1, 10)),
+        )
+        .expect("Append failed");
+        let first = BatchTransition::from(first);
+        let second = BatchTransition::from(second);
+
+        let chained =
+            chain_transitions(&[first.clone(), second.clone()]).expect("Batches do not chain");
+        assert_eq!(chained.block_range, Some((1, 20)));
+        assert_eq!(chained.initial_elements_count, 0);
+        assert!(chained.ends_at(&second.final_peaks, second.elements_count, 20));
+
+        // Out of order or with a gap in the blocks
+        assert_eq!(
+            chain_transitions(&[second.clone(), first.clone()]).unwrap_err(),
+            AggregationError::Disconnected(1)
+        );
+        let mut gap = second;
+        gap.block_range = Some((1, 9));
+        assert_eq!(
+            chain_transitions(&[first, gap]).unwrap_err(),
+            AggregationError::Disconnected(1)
+        );
+        assert_eq!(chain_transitions(&[]).unwrap_err(), AggregationError::Empty);
+
+        // Each batch is adjacent to the one before it, but the third one
+        // appends the blocks of the first one again
+        let first = append_blocks(None, (100, 200));
+        let second = append_blocks(Some(&first), (201, 300));
+        let third = append_blocks(Some(&second), (100, 200));
+        let zigzag = [&first, &second, &third].map(BatchTransition::from);
+        assert!(zigzag[1].connects_to(&zigzag[2]));
+        assert_eq!(
+            chain_transitions(&zigzag).unwrap_err(),
+            AggregationError::Disconnected(2)
+        );
+
+        // Or extends them on the other side
+        let below = append_blocks(Some(&second), (50, 99));
+        let switched = [&first, &second, &below].map(BatchTransition::from);
+        assert_eq!(
+            chain_transitions(&switched).unwrap_err(),
+            AggregationError::Disconnected(2)
+        );
+        assert!(chain_transitions(&zigzag[..2]).is_ok());
+    }
+
+    /// Output of a batch appending `blocks`, in ascending order, after `previous`.
+    fn append_blocks(previous: Option<&GuestOutput>, blocks: (u64, u64)) -> GuestOutput {
+        let mmr_input = GuestInput {
+            initial_peaks: previous.map_or(vec![], |output| output.final_peaks.clone()),
+            elements_count: previous.map_or(0, |output| output.elements_count),
+            leaves_count: previous.map_or(0, |output| output.leaves_count),
+            new_elements: (blocks.0..=blocks.1).map(Felt::from).collect(),
+            previous_proofs: None,
+            method_id: [0; 8],
+            aggregation_method_id: [0; 8],
+        };
+        append_batch(HasherKind::Poseidon, mmr_input, Some(blocks)).expect("Append failed")
+    }
+
+    #[test]
+    fn test_only_batches_from_the_empty_mmr_are_chained() {
+        let first = append_blocks(None, (1, 4));
+        assert!(first.chained);
+        assert!(!append_blocks(Some(&first), (5, 8)).chained);
+
+        // An empty MMR claiming leaves or peaks is rejected, not chained
+        let fake_empty_inputs = [
+            (vec![], 4),
+            (first.final_peaks.clone(), 0),
+            (first.final_peaks.clone(), first.leaves_count),
+        ];
+        for (initial_peaks, leaves_count) in fake_empty_inputs {
+            let mmr_input = GuestInput {
+                initial_peaks,
+                elements_count: 0,
+                leaves_count,
+                new_elements: vec![Felt::from(5u64)],
+                previous_proofs: None,
+                method_id: [0; 8],
+                aggregation_method_id: [0; 8],
+            };
+            assert!(!mmr_input.starts_empty());
+            assert!(matches!(
+                append_batch(HasherKind::Poseidon, mmr_input, Some((5, 5))),
+                Err(MMRError::InvalidInitialState { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_connects_to_rejects_blocks_already_chained() {
+        let first_output = append_blocks(None, (100, 200));
+        let first = BatchTransition::from(&first_output);
+
+        // Blocks that do not touch the previous batch
+        let distant = BatchTransition::from(append_blocks(Some(&first_output), (500, 600)));
+        assert!(!first.connects_to(&distant));
+
+        // Continuing the chain, which then covers blocks 100 to 300
+        let second_output = append_blocks(Some(&first_output), (201, 300));
+        let mut second = BatchTransition::from(&second_output);
+        assert!(first.connects_to(&second));
+        second.chain_block_range = merge_block_ranges(first.chain_block_range, second.block_range);
+
+        // Blocks 100 to 200 sit right below the second batch, but were
+        // already appended by the first one
+        let repeated = BatchTransition::from(append_blocks(Some(&second_output), (100, 200)));
+        assert!(!second.connects_to(&repeated));
+        let next = BatchTransition::from(append_blocks(Some(&second_output), (301, 400)));
+        assert!(second.connects_to(&next));
+
+        // Nor can generic leaves continue a chain of blocks
+        let mut leaves = next;
+        leaves.mode = AppendMode::GenericLeaves;
+        assert!(!second.connects_to(&leaves));
+    }
+
+    #[test]
+    fn test_parse_element_rejects_invalid_values() {
+        // The field prime and anything above it does not fit in a felt
+        let prime = "0x800000000000011000000000000000000000000000000000000000000000001";
+        let max = "0x800000000000011000000000000000000000000000000000000000000000000";
+        let oversized = format!("0x1{}", "0".repeat(64));
+
+        for value in ["", "0x", "0xzz", prime, oversized.as_str()] {
+            assert!(matches!(
+                parse_element(value),
+                Err(MMRError::InvalidElement(e)) if e == value
+            ));
+        }
+        assert_eq!(parse_element(max).unwrap(), Felt::MAX);
+        assert_eq!(parse_element("0x00ff").unwrap(), felt("0xff"));
+    }
+
+    #[test]
+    fn test_felt_serialization() {
+        let proof = Proof {
+            element_index: 1,
+            element_hash: felt("0xff"),
+            siblings_hashes: vec![felt("0x2")],
+            peaks_hashes: vec![Felt::MAX],
+            elements_count: 1,
+            hasher: HasherKind::Poseidon,
+        };
+
+        // Hex strings in human readable formats
+        let value = serde_json::to_value(&proof).unwrap();
+        assert_eq!(value["element_hash"], json!("0xff"));
+        assert_eq!(value["siblings_hashes"], json!(["0x2"]));
+        assert_eq!(serde_json::from_value::<Proof>(value).unwrap(), proof);
+
+        // 32 raw bytes otherwise
+        let encoded = bincode::serialize(&proof).unwrap();
+        let element_hash = &encoded[16..48];
+        assert_eq!(element_hash, felt("0xff").to_bytes_be());
+        assert_eq!(bincode::deserialize::<Proof>(&encoded).unwrap(), proof);
+
+        // Values not below the field prime are rejected
+        let mut oversized = encoded.clone();
+        oversized[16..48].copy_from_slice(&[0xff; 32]);
+        assert!(bincode::deserialize::<Proof>(&oversized).is_err());
+    }
+
+    #[test]
+    fn test_block_hash_element() {
+        let block_hash = "0x88e96d4537bea4d9c05d12549907b32561d3bf31f45aae734cdc119f13406cb6";
+        let element =
+            block_hash_element(HasherKind::Poseidon, block_hash).expect("Invalid block hash");
+        assert_eq!(
+            element,
+            poseidon_hash(
+                felt("0x88e96d4537bea4d9c05d12549907b325"),
+                felt("0x61d3bf31f45aae734cdc119f13406cb6")
+            )
+        );
+
+        // Hashes equal modulo the field prime are still distinct leaves
+        let reduced = "0x00e96d4537bea3b8c05d12549907b32561d3bf31f45aae734cdc119f13406ca5";
+        assert_ne!(
+            block_hash_element(HasherKind::Poseidon, reduced).unwrap(),
+            element
+        );
+
+        // Block hashes must be exactly 32 bytes
+        assert!(matches!(
+            block_hash_element(HasherKind::Poseidon, "0x1234"),
+            Err(MMRError::InvalidElement(_))
+        ));
+    }
+
+    #[test]
+    fn test_prague_header_hash() {
+        // Prague devnet header used by the alloy-consensus test vectors
+        let header = serde_json::from_value(json!({
+            "block_hash": "0x661da523f3e44725f3a1cee38183d35424155a05674609a9f6ed81243adf9e26",
+            "number": 0x315,
+            "parent_hash": "0x60f1563d2c572116091a4b91421d8d972118e39604d23455d841f9431cea4b6a",
+            "sha3_uncles": "0x1dcc4de8dec75d7aab85b567b6ccd41ad312451b948a7413f0a142fd40d49347",
+            "miner": "0xf97e180c050e5ab072211ad2c213eb5aee4df134",
+            "state_root": "0x8101d88f2761eb9849634740f92fe09735551ad5a4d5e9da9bcae1ef4726a475",
+            "transaction_root": "0xf543eb3d405d2d6320344d348b06703ff1abeef71288181a24061e53f89bb5ef",
+            "receipts_root": "0xeaa8c40899a61ae59615cf9985f5e2194f8fd2b57d273be63bde6733e89b12ab",
+            "logs_bloom": format!("0x{}", "00".repeat(256)),
+            "difficulty": "0x0",
+            "gas_limit": 0x1c9c380,
+            "gas_used": 0x5208,
+            "timestamp": "0x6712ba6e",
+            "extra_data": "0xd883010e0c846765746888676f312e32332e32856c696e7578",
+            "mix_hash": "0xe6d9c084dd36560520d5776a5387a82fb44793c9cd1b69afb61d53af29ee64b0",
+            "nonce": "0x0000000000000000",
+            "base_fee_per_gas": "0x7",
+            "withdrawals_root": "0x56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421",
+            "blob_gas_used": "0x20000",
+            "excess_blob_gas": "0x40000",
+            "parent_beacon_block_root": "0xd0bdb48ab45028568e66c8ddd600ac4c2a52522714bbfbf00ea6d20ba40f3ae2",
+        }))
+        .expect("Invalid header");
+        let requests_hash = "0x6036c41849da9c076ed79654d434017387a88fb833c2856b32e18218b3341c5f";
+
+        let block_hash = compute_block_hash(&header, Some(requests_hash)).expect("Invalid header");
+        assert_eq!(
+            block_hash,
+            "0x661da523f3e44725f3a1cee38183d35424155a05674609a9f6ed81243adf9e26"
+        );
+
+        // Without its requests hash the header is taken for a Cancun header
+        let block_hash = compute_block_hash(&header, None).expect("Invalid header");
+        assert!(!block_hashes_match(&block_hash, &header.block_hash));
+    }
+
+    fn root_with_verified_leaves<H: MmrHasher>() -> Felt {
+        let mut guest_mmr =
+            GuestMMR::<H>::with_hasher(vec![], 0, 0).expect("Invalid initial state");
+        let leaves = ["0x1", "0x2", "0x3", "0x4", "0x5"];
+        let element_indices: Vec<usize> = leaves
+            .iter()
+            .map(|leaf| {
+                guest_mmr
+                    .append(felt(leaf))
+                    .expect("Append failed")
+                    .element_index
+            })
+            .collect();
+
+        let bag = guest_mmr.bag_the_peaks().expect("Bag the peaks failed");
+        let root_hash = guest_mmr
+            .calculate_root_hash(&bag, guest_mmr.get_elements_count())
+            .expect("Calculate root hash failed");
+
+        for (leaf, element_index) in leaves.iter().zip(element_indices) {
+            let proof = build_inclusion_proof(&guest_mmr, element_index, leaf);
+            assert!(verify_inclusion_proof(&proof, &root_hash).expect("Verification failed"));
+        }
+        root_hash
+    }
+
+    // Known answers computed independently of this crate: keccak256 and
+    // sha256 over the 32 byte big endian values with the top 5 bits cleared,
+    // StarkNet Poseidon otherwise.
+    #[test]
+    fn test_hashers() {
+        assert_eq!(
+            root_with_verified_leaves::<PoseidonHasher>(),
+            felt("0x5f71f4a9b08283fa7f2f99e4c5a4dd3def691e242f3e2c04d01496f70ae46d3")
+        );
+        assert_eq!(
+            root_with_verified_leaves::<KeccakHasher>(),
+            felt("0x420ce2539927c971f94519917f42c54353fa38e0066639b31723e7a8ee8e0db")
+        );
+        assert_eq!(
+            root_with_verified_leaves::<Sha256Hasher>(),
+            felt("0x69153b826487579f412fab106ed00c070dc65c9b6f643883c26af4624b31fdb")
+        );
+    }
+
+    #[test]
+    fn test_block_hash_element_hashers() {
+        let block_hash = "0x88e96d4537bea4d9c05d12549907b32561d3bf31f45aae734cdc119f13406cb6";
+        let element = |hasher| block_hash_element(hasher, block_hash).expect("Invalid block hash");
+
+        assert_eq!(
+            element(HasherKind::Poseidon),
+            felt("0x3f2a6a3235c10e466ceae6ecf6525da6594c0299ce17c0f2cff0b2d8fe523a8")
+        );
+        assert_eq!(
+            element(HasherKind::Keccak),
+            felt("0x64a84a3bd778aa01aef2a0d44595ab2defcd5412b5bb6b51756eab5496611e7")
+        );
+        assert_eq!(
+            element(HasherKind::Sha256),
+            felt("0x31bd5e3850ca174820dd24e272e08d7945bab31d10dd83ff6026ea34d031611")
+        );
+    }
+}
+
+/// Property tests checking the guest MMR against the host `mmr` crate that
+/// backs the accumulator's store.
+#[cfg(test)]
+mod host_comparison {
+    use super::block_on;
+    use host::consistency::{synthetic_leaves, verify_consistency};
+    use proptest::prelude::*;
+    use starknet_crypto::Felt;
+
+    /// Random felts, kept below 2^251 so that they are always valid elements.
+    fn element() -> impl Strategy<Value = Felt> {
+        prop::array::uniform32(any::<u8>()).prop_map(|mut bytes| {
+            bytes[0] &= 0x07;
+            Felt::from_bytes_be(&bytes)
+        })
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn test_guest_mmr_matches_host_mmr(
+            elements in prop::collection::vec(element(), 1..64),
+            batch_size in 1usize..16,
+        ) {
+            let divergence = block_on(verify_consistency(&elements, batch_size))
+                .expect("Replay failed");
+            prop_assert!(divergence.is_none(), "{}", divergence.unwrap());
+        }
+    }
+
+    #[test]
+    fn test_guest_mmr_matches_host_mmr_across_peak_merges() {
+        // 1000 leaves cross every merge height up to 2^9 in a single batch
+        let leaves = synthetic_leaves(0, 1000);
+        let divergence =
+            block_on(verify_consistency(&leaves, leaves.len())).expect("Replay failed");
+        assert_eq!(divergence, None);
+    }
+}
+
+/// Tests of the accumulator over a temporary SQLite store. Nothing is
+/// proven: the batch guest is only executed.
+#[cfg(test)]
+mod accumulator_store {
+    use super::block_on;
+    use host::accumulator::AccumulatorBuilder;
+    use host::checkpoint::{load_last_checkpoint, save_checkpoint};
+    use host::consistency::synthetic_leaves;
+    use host::proof_generator::ProofGenerator;
+    use host::types::ProofType;
+    use methods::{MMR_GUEST_ELF, MMR_GUEST_ID};
+    use mmr_accumulator::processor_utils::create_database_file;
+    use risc0_zkvm::{FakeReceipt, InnerReceipt, Receipt, ReceiptClaim};
+    use sqlx::SqlitePool;
+    use tempfile::TempDir;
+
+    /// A new MMR store in a temporary directory, removed with it.
+    fn temp_store() -> (TempDir, String) {
+        let dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let store_path =
+            create_database_file(&dir.path().to_path_buf(), 0).expect("Failed to create store");
+        (dir, store_path)
+    }
+
+    /// Accumulator over `store_path` executing batches of 2 leaves.
+    async fn open_store(store_path: &str) -> AccumulatorBuilder {
+        let proof_generator =
+            ProofGenerator::new(MMR_GUEST_ELF, MMR_GUEST_ID).with_execute_only(true);
+        AccumulatorBuilder::new(store_path, proof_generator, 2)
+            .await
+            .expect("Failed to open store")
+    }
+
+    async fn connect(store_path: &str) -> SqlitePool {
+        SqlitePool::connect(&format!("sqlite://{}", store_path))
+            .await
+            .expect("Failed to connect to store")
+    }
+
+    async fn execute(store_path: &str, statement: &str) {
+        let pool = connect(store_path).await;
+        sqlx::query(statement)
+            .execute(&pool)
+            .await
+            .expect("Failed to update store");
+        pool.close().await;
+    }
+
+    /// Record the last batch again, as if it had been proven as `proof`.
+    async fn checkpoint_as(store_path: &str, proof: ProofType, chain_receipt: Option<Receipt>) {
+        let pool = connect(store_path).await;
+        let mut checkpoint = load_last_checkpoint(&pool)
+            .await
+            .expect("Failed to load checkpoint")
+            .expect("No checkpoint");
+        checkpoint.proof = proof;
+        checkpoint.chain_receipt = chain_receipt;
+        let mut conn = pool.acquire().await.expect("Failed to connect to store");
+        save_checkpoint(&mut conn, &checkpoint)
+            .await
+            .expect("Failed to save checkpoint");
+        drop(conn);
+        pool.close().await;
+    }
+
+    /// Receipt of the batch guest for `journal` that only verifies in dev mode.
+    fn fake_receipt(journal: &[u8]) -> Receipt {
+        let claim = ReceiptClaim::ok(MMR_GUEST_ID, journal.to_vec());
+        Receipt::new(
+            InnerReceipt::Fake(FakeReceipt::new(claim)),
+            journal.to_vec(),
+        )
+    }
+
+    #[test]
+    fn test_resume_rolls_back_state_not_matching_the_checkpoint() {
+        block_on(async {
+            // A crash while writing a batch after the third leaf, with the
+            // counters or a peak already written
+            let partial_writes = [
+                "UPDATE store SET value = '7' WHERE key LIKE '%elements_count'",
+                "UPDATE store SET value = '4' WHERE key LIKE '%leaves_count'",
+                "UPDATE store SET value = '0x1' WHERE key LIKE '%:hashes:4'",
+            ];
+
+            for partial_write in partial_writes {
+                let (_dir, store_path) = temp_store();
+                let mut builder = open_store(&store_path).await;
+                builder
+                    .append_leaves(synthetic_leaves(0, 3))
+                    .await
+                    .expect("Append failed");
+                let checkpointed = builder.state().await.expect("Failed to read state");
+                drop(builder);
+
+                execute(&store_path, partial_write).await;
+
+                let builder = open_store(&store_path).await;
+                let resumed = builder.state().await.expect("Failed to read state");
+                assert_eq!(resumed, checkpointed, "Not rolled back: {}", partial_write);
+            }
+        });
+    }
+
+    #[test]
+    fn test_resume_chains_from_the_last_checkpoint() {
+        block_on(async {
+            let (_dir, store_path) = temp_store();
+            let mut builder = open_store(&store_path).await;
+            builder
+                .append_leaves(synthetic_leaves(0, 3))
+                .await
+                .expect("Append failed");
+            let journal = builder
+                .previous_proof()
+                .expect("No previous proof")
+                .journal
+                .clone();
+            drop(builder);
+
+            // Unproven batches are chained without a receipt
+            let builder = open_store(&store_path).await;
+            let previous_proof = builder.previous_proof().expect("No previous proof");
+            assert_eq!(previous_proof.journal, journal);
+            assert_eq!(previous_proof.method_id, MMR_GUEST_ID);
+            assert!(builder.previous_receipt().is_none());
+            drop(builder);
+
+            let receipt = fake_receipt(&journal);
+            let stark = ProofType::Stark {
+                receipt: receipt.clone(),
+                image_id: Vec::new(),
+                method_id: MMR_GUEST_ID,
+            };
+            let groth16 = ProofType::Groth16 {
+                receipt: receipt.clone(),
+                calldata: Vec::new(),
+            };
+
+            // STARK receipts, and the succinct receipt of a Groth16 proof,
+            // are chained into the next batch
+            for (proof, chain_receipt) in [(stark, None), (groth16.clone(), Some(receipt.clone()))]
+            {
+                checkpoint_as(&store_path, proof, chain_receipt).await;
+                let builder = open_store(&store_path).await;
+                let previous_proof = builder.previous_proof().expect("No previous proof");
+                assert_eq!(previous_proof.journal, journal);
+                assert_eq!(previous_proof.method_id, MMR_GUEST_ID);
+                assert_eq!(
+                    builder.previous_receipt().map(|r| &r.journal.bytes),
+                    Some(&journal)
+                );
+            }
+
+            // Without its succinct receipt, a Groth16 proof cannot be chained from
+            checkpoint_as(&store_path, groth16, None).await;
+            let builder = open_store(&store_path).await;
+            assert!(builder.previous_proof().is_none());
+            assert!(builder.previous_receipt().is_none());
+        });
+    }
+
+    #[test]
+    fn test_resume_takes_a_store_without_checkpoints_as_it_is() {
+        block_on(async {
+            let (_dir, store_path) = temp_store();
+            let mut builder = open_store(&store_path).await;
+            builder
+                .append_leaves(synthetic_leaves(0, 3))
+                .await
+                .expect("Append failed");
+            let written = builder.state().await.expect("Failed to read state");
+            drop(builder);
+
+            execute(&store_path, "DELETE FROM batch_checkpoints").await;
+
+            // Nothing is rolled back, and the next batch starts a new chain
+            let mut builder = open_store(&store_path).await;
+            assert_eq!(
+                builder.state().await.expect("Failed to read state"),
+                written
+            );
+            assert!(builder.previous_proof().is_none());
+
+            builder
+                .append_leaves(synthetic_leaves(1, 2))
+                .await
+                .expect("Append failed");
+            let extended = builder.state().await.expect("Failed to read state");
+            assert_eq!(extended.leaves_count, 5);
+        });
+    }
+
+    #[test]
+    fn test_resume_rolls_back_an_interrupted_parallel_build() {
+        block_on(async {
+            let (_dir, store_path) = temp_store();
+            let mut builder = open_store(&store_path).await;
+            builder
+                .append_leaves(synthetic_leaves(0, 2))
+                .await
+                .expect("Append failed");
+            let chainable = builder.state().await.expect("Failed to read state");
+            let journal = builder
+                .previous_proof()
+                .expect("No previous proof")
+                .journal
+                .clone();
+            builder
+                .append_leaves(synthetic_leaves(1, 4))
+                .await
+                .expect("Append failed");
+            drop(builder);
+
+            // Segments of a parallel build written without their aggregation
+            let segments = "UPDATE batch_checkpoints SET chainable = FALSE WHERE id > (SELECT MIN(id) FROM batch_checkpoints)";
+            execute(&store_path, segments).await;
+
+            // The MMR resumes from the last chainable checkpoint, and the
+            // segments are dropped for good
+            for _ in 0..2 {
+                let builder = open_store(&store_path).await;
+                assert_eq!(
+                    builder.state().await.expect("Failed to read state"),
+                    chainable
+                );
+                let previous_proof = builder.previous_proof().expect("No previous proof");
+                assert_eq!(previous_proof.journal, journal);
+            }
+            let pool = connect(&store_path).await;
+            let (checkpoints,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM batch_checkpoints")
+                .fetch_one(&pool)
+                .await
+                .expect("Failed to count checkpoints");
+            pool.close().await;
+            assert_eq!(checkpoints, 1);
+
+            // Without any, back to the empty MMR
+            execute(
+                &store_path,
+                "UPDATE batch_checkpoints SET chainable = FALSE",
+            )
+            .await;
+            let mut builder = open_store(&store_path).await;
+            assert_eq!(
+                builder
+                    .state()
+                    .await
+                    .expect("Failed to read state")
+                    .elements_count,
+                0
+            );
+            assert!(builder.previous_proof().is_none());
+            builder
+                .append_leaves(synthetic_leaves(0, 2))
+                .await
+                .expect("Append failed");
+            assert_eq!(
+                builder.state().await.expect("Failed to read state"),
+                chainable
+            );
+        });
+    }
+
+    /// Every key and value of the MMR store.
+    async fn store_rows(store_path: &str) -> Vec<(String, String)> {
+        let pool = connect(store_path).await;
+        let rows = sqlx::query_as("SELECT key, value FROM store ORDER BY key")
+            .fetch_all(&pool)
+            .await
+            .expect("Failed to read store");
+        pool.close().await;
+        rows
+    }
+
+    #[test]
+    fn test_failed_batch_write_leaves_the_store_untouched() {
+        block_on(async {
+            let (_dir, store_path) = temp_store();
+            let mut builder = open_store(&store_path).await;
+            builder
+                .append_leaves(synthetic_leaves(0, 3))
+                .await
+                .expect("Append failed");
+            let state = builder.state().await.expect("Failed to read state");
+            let rows = store_rows(&store_path).await;
+
+            // The checkpoint is the last write of a batch, after its nodes,
+            // counters and block index
+            execute(
+                &store_path,
+                "CREATE TRIGGER fail_checkpoint BEFORE INSERT ON batch_checkpoints BEGIN SELECT RAISE(ABORT, 'checkpoint failure'); END",
+            )
+            .await;
+            assert!(builder.append_leaves(synthetic_leaves(1, 2)).await.is_err());
+
+            assert_eq!(builder.state().await.expect("Failed to read state"), state);
+            assert_eq!(store_rows(&store_path).await, rows);
+        });
+    }
+
+    #[test]
+    fn test_check_consistency_of_a_written_store() {
+        block_on(async {
+            let (_dir, store_path) = temp_store();
+            let mut builder = open_store(&store_path).await;
+            builder
+                .append_leaves(synthetic_leaves(0, 7))
+                .await
+                .expect("Append failed");
+            builder
+                .check_consistency()
+                .await
+                .expect("Written store is inconsistent");
+
+            // Node 6 is the parent of leaves 4 and 5
+            execute(
+                &store_path,
+                "UPDATE store SET value = '0x1' WHERE key LIKE '%:hashes:6'",
+            )
+            .await;
+            assert!(builder.check_consistency().await.is_err());
+
+            // A missing node is reported too
+            execute(&store_path, "DELETE FROM store WHERE key LIKE '%:hashes:6'").await;
+            assert!(builder.check_consistency().await.is_err());
+        });
+    }
+}
+
+/// Tests of the batch size tuning from measured cycles.
+#[cfg(test)]
+mod batch_tuning {
+    use host::batch_tuner::{BatchSizeTuner, TuningTarget};
+    use host::types::ProofStats;
+
+    fn stats(user_cycles: u64) -> ProofStats {
+        ProofStats {
+            total_cycles: user_cycles,
+            user_cycles,
+            segments: 1,
+            elapsed_ms: 0,
+            phase_cycles: None,
+        }
+    }
+
+    #[test]
+    fn test_batch_size_is_kept_within_tolerance() {
+        // 1000 cycles per header fill 90% of a segment with 943 headers
+        let mut tuner = BatchSizeTuner::new(TuningTarget::Segments(1), 8192);
+        assert_eq!(tuner.batch_size(), None);
+        assert_eq!(tuner.observe(100, &stats(100_000), 900), None);
+        assert_eq!(tuner.batch_size(), Some(943));
+    }
+
+    #[test]
+    fn test_batch_size_changes_past_tolerance() {
+        let mut tuner = BatchSizeTuner::new(TuningTarget::Segments(1), 8192);
+        assert_eq!(tuner.observe(100, &stats(100_000), 500), Some(943));
+
+        // 4 segments of 256 MB fit in 1 GB
+        let mut tuner = BatchSizeTuner::new(
+            TuningTarget::Memory {
+                budget_bytes: 1 << 30,
+                segment_bytes: 256 << 20,
+            },
+            8192,
+        );
+        assert_eq!(tuner.observe(100, &stats(100_000), 500), Some(3774));
+    }
+
+    #[test]
+    fn test_batch_size_is_clamped_to_max() {
+        let mut tuner = BatchSizeTuner::new(TuningTarget::Segments(1), 8192);
+        assert_eq!(tuner.observe(100, &stats(100), 100), Some(8192));
+        assert_eq!(tuner.batch_size(), Some(8192));
+    }
+}
+
+/// Tests of the header sources the accumulator reads blocks from.
+#[cfg(test)]
+mod header_sources {
+    use super::block_on;
+    use guest_types::header_rlp::{block_hashes_match, compute_block_hash};
+    use host::header_source::{check_complete, FetchedHeader, FileHeaderSource, HeaderSource};
+
+    /// Prague devnet header, recorded with its `requestsHash`.
+    const PRAGUE_HEADER: &str =
+        concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/prague_header.jsonl");
+
+    fn prague_header() -> FetchedHeader {
+        let source = FileHeaderSource::open(PRAGUE_HEADER).expect("Failed to read fixture");
+        let mut headers =
+            block_on(source.headers_in_range(0x315, 0x315)).expect("Missing fixture header");
+        headers.pop().expect("Missing fixture header")
+    }
+
+    /// Copies of the fixture header numbered `numbers`, in that order.
+    fn numbered(numbers: &[u64]) -> Vec<FetchedHeader> {
+        let header = prague_header();
+        numbers
+            .iter()
+            .map(|&number| {
+                let mut header = header.clone();
+                header.header.number = number as _;
+                header
+            })
+            .collect()
+    }
+
+    fn block_numbers(headers: &[FetchedHeader]) -> Vec<u64> {
+        headers
+            .iter()
+            .map(|fetched| fetched.header.number as u64)
+            .collect()
+    }
+
+    #[test]
+    fn test_file_source_reads_the_requests_hash() {
+        let fetched = prague_header();
+        assert_eq!(
+            fetched.requests_hash.as_deref(),
+            Some("0x6036c41849da9c076ed79654d434017387a88fb833c2856b32e18218b3341c5f")
+        );
+
+        let block_hash = compute_block_hash(&fetched.header, fetched.requests_hash.as_deref())
+            .expect("Invalid header");
+        assert!(block_hashes_match(&block_hash, &fetched.header.block_hash));
+    }
+
+    #[test]
+    fn test_file_source_returns_headers_in_ascending_order() {
+        let source =
+            FileHeaderSource::from_headers(numbered(&[13, 10, 12, 11])).expect("Invalid headers");
+
+        let headers = block_on(source.headers_in_range(10, 13)).expect("Missing headers");
+        assert_eq!(block_numbers(&headers), vec![10, 11, 12, 13]);
+        let headers = block_on(source.headers_in_range(11, 12)).expect("Missing headers");
+        assert_eq!(block_numbers(&headers), vec![11, 12]);
+        assert_eq!(block_on(source.finalized_block_number()).unwrap(), 13);
+
+        // Blocks past the end of the file are missing, not skipped
+        assert!(block_on(source.headers_in_range(12, 14)).is_err());
+    }
+
+    #[test]
+    fn test_file_source_rejects_duplicate_blocks() {
+        assert!(FileHeaderSource::from_headers(numbered(&[10, 11, 10])).is_err());
+    }
+
+    #[test]
+    fn test_check_complete() {
+        assert!(check_complete(&numbered(&[10, 11, 12]), 10, 12).is_ok());
+
+        // Gaps, whether or not the count matches
+        assert!(check_complete(&numbered(&[10, 12]), 10, 12).is_err());
+        assert!(check_complete(&numbered(&[10, 12, 13]), 10, 12).is_err());
+        // Duplicates, in place of a missing block or in excess
+        assert!(check_complete(&numbered(&[10, 11, 11]), 10, 12).is_err());
+        assert!(check_complete(&numbered(&[10, 11, 11, 12]), 10, 12).is_err());
+        // Out of order
+        assert!(check_complete(&numbered(&[10, 12, 11]), 10, 12).is_err());
+    }
+}
+
+/// Run `future` to completion, for the tests of the async host API.
+#[cfg(test)]
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    tokio::runtime::Runtime::new()
+        .expect("Failed to start runtime")
+        .block_on(future)
 }
@@ -0,0 +1,6 @@
+-- Maps each accumulated block to its position in the MMR
+CREATE TABLE IF NOT EXISTS block_leaf_index (
+    block_number INTEGER PRIMARY KEY,
+    leaf_index INTEGER NOT NULL,
+    element_index INTEGER NOT NULL
+);
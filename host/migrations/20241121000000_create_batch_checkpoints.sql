@@ -0,0 +1,12 @@
+-- One row per completed batch, used to resume accumulation after a restart
+CREATE TABLE IF NOT EXISTS batch_checkpoints (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    start_block INTEGER NOT NULL,
+    end_block INTEGER NOT NULL,
+    journal BLOB NOT NULL,
+    proof BLOB NOT NULL,
+    peaks TEXT NOT NULL,
+    elements_count INTEGER NOT NULL,
+    leaves_count INTEGER NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,2 @@
+-- Journal format of each checkpointed batch, 'full' or 'compact'
+ALTER TABLE batch_checkpoints ADD COLUMN journal_format TEXT NOT NULL DEFAULT 'full';
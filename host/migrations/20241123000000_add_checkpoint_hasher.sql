@@ -0,0 +1,2 @@
+-- Hasher each checkpointed batch was built with, see HasherKind
+ALTER TABLE batch_checkpoints ADD COLUMN hasher TEXT NOT NULL DEFAULT 'poseidon';
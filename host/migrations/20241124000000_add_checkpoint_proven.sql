@@ -0,0 +1,2 @@
+-- Whether each checkpointed batch was proven, or only executed with --execute-only
+ALTER TABLE batch_checkpoints ADD COLUMN proven BOOLEAN NOT NULL DEFAULT TRUE;
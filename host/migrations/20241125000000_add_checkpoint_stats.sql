@@ -0,0 +1,7 @@
+-- Proving stats of each checkpointed batch, NULL for batches checkpointed before they were recorded.
+-- phase_cycles is the JSON encoded PhaseCycles reported by the guest.
+ALTER TABLE batch_checkpoints ADD COLUMN total_cycles INTEGER;
+ALTER TABLE batch_checkpoints ADD COLUMN user_cycles INTEGER;
+ALTER TABLE batch_checkpoints ADD COLUMN segments INTEGER;
+ALTER TABLE batch_checkpoints ADD COLUMN elapsed_ms INTEGER;
+ALTER TABLE batch_checkpoints ADD COLUMN phase_cycles TEXT;
@@ -0,0 +1,3 @@
+-- Bincode encoded succinct receipt the next batch chains from, for batches proven with Groth16.
+-- NULL for other proofs, and for Groth16 batches checkpointed before it was recorded.
+ALTER TABLE batch_checkpoints ADD COLUMN chain_receipt BLOB;
@@ -0,0 +1,3 @@
+-- Whether later batches may chain from each checkpointed batch. FALSE for the segments
+-- of a parallel build, which are proven without the batches before them.
+ALTER TABLE batch_checkpoints ADD COLUMN chainable BOOLEAN NOT NULL DEFAULT TRUE;
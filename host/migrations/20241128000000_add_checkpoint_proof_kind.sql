@@ -0,0 +1,3 @@
+-- Guest each checkpointed journal comes from, 'batch' or 'aggregation'. A parallel build is
+-- checkpointed with the journal of its aggregation after its segments, which later batches chain from.
+ALTER TABLE batch_checkpoints ADD COLUMN proof_kind TEXT NOT NULL DEFAULT 'batch';
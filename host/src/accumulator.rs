@@ -1,15 +1,38 @@
 // host/src/accumulator.rs
-use crate::proof_generator::ProofGenerator;
-use crate::types::{BatchResult, ProofType};
-use db_access::rpc::get_block_headers_in_range;
+use crate::artifacts::ArtifactStore;
+use crate::batch_tuner::BatchSizeTuner;
+use crate::checkpoint::{
+    delete_unchainable_checkpoints, load_last_chainable_checkpoint, load_last_checkpoint,
+    save_checkpoint,
+};
+use crate::header_source::{FetchedHeader, HeaderSource, RpcHeaderSource};
+use crate::proof_generator::{ProofGenerator, SideChannel};
+use crate::types::{BatchCheckpoint, BatchResult, GeneratedProof, MmrState, ProofType};
+use block_validity::BlockHeader;
 use eyre::Result;
-use guest_types::{BatchProof, CombinedInput, GuestInput, GuestOutput};
-use mmr::{find_peaks, PeaksOptions};
-use mmr_accumulator::{
-    ethereum::get_finalized_block_hash, processor_utils::*, store::StoreManager, MMR,
+use guest_mmr::append_batch;
+use guest_types::{
+    calculate_root_hash, felt,
+    inclusion::{children_indices, find_siblings, hash_children},
+    AggregationInput, AggregationOutput, AppendMode, BatchProof, BatchSideOutput, CombinedInput,
+    CompactGuestOutput, GuestInput, GuestOutput, HasherKind, InclusionInput, JournalFormat, Proof,
+    ProofKind,
 };
+use methods::MMR_AGGREGATE_ID;
+use mmr::{find_peaks, PeaksOptions};
+use mmr_accumulator::{processor_utils::*, store::StoreManager, MMR};
+use risc0_zkvm::{AssumptionReceipt, Receipt, ReceiptClaim};
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::SqliteConnection;
+use starknet_crypto::Felt;
+use std::sync::Arc;
 use store::{SqlitePool, SubKey};
-use tracing::{debug, info};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tracing::{debug, info, warn};
+
+/// Number of blocks executed to calibrate the batch size.
+const SAMPLE_BLOCKS: u64 = 32;
 
 pub struct AccumulatorBuilder {
     batch_size: u64,
@@ -19,7 +42,42 @@ pub struct AccumulatorBuilder {
     proof_generator: ProofGenerator,
     total_batches: u64,
     current_batch: u64,
-    previous_proofs: Vec<BatchProof>,
+    previous_proof: Option<BatchProof>,
+    previous_receipt: Option<Receipt>,
+    // Image ID of the aggregation guest, whose proofs batches chain from
+    // after a parallel build
+    aggregation_method_id: [u32; 8],
+    journal_format: JournalFormat,
+    hasher: HasherKind,
+    batch_size_tuner: Option<BatchSizeTuner>,
+    parallel_proving: Option<ParallelProving>,
+    artifacts: Option<ArtifactStore>,
+    header_source: Arc<dyn HeaderSource>,
+}
+
+/// Worker pool and aggregation guest of parallel proving.
+#[derive(Clone)]
+struct ParallelProving {
+    workers: usize,
+    aggregation_prover: ProofGenerator,
+}
+
+/// Order in which a build appends blocks, within and across batches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    /// Ascending block numbers
+    Forward,
+    /// Descending block numbers
+    Backward,
+}
+
+/// A batch ready to be proven, with the output the guest should commit.
+struct PreparedBatch {
+    start_block: u64,
+    end_block: u64,
+    headers: Vec<BlockHeader>,
+    input: CombinedInput,
+    expected_output: GuestOutput,
 }
 
 impl AccumulatorBuilder {
@@ -28,12 +86,63 @@ impl AccumulatorBuilder {
         proof_generator: ProofGenerator,
         batch_size: u64,
     ) -> Result<Self> {
+        if batch_size == 0 {
+            return Err(eyre::eyre!("The batch size must be at least 1"));
+        }
         let (store_manager, mmr, pool) = initialize_mmr(store_path).await?;
 
         // Configure pool with appropriate settings
         sqlx::migrate!("./migrations").run(&pool).await?;
 
-        Ok(Self {
+        let mut builder = Self::with_store(store_manager, mmr, pool, proof_generator, batch_size);
+        builder.resume_from_checkpoint().await?;
+
+        Ok(builder)
+    }
+
+    /// Open the store at `store_path` for reading its state only: no
+    /// migration is run and no batch is rolled back, so a partially applied
+    /// batch is shown as it was left. Nothing can be appended through it.
+    pub async fn open_read_only(store_path: &str, proof_generator: ProofGenerator) -> Result<Self> {
+        // Opening a missing file would create an empty store
+        if !std::path::Path::new(store_path).exists() {
+            return Err(eyre::eyre!("No MMR store at {}", store_path));
+        }
+        let (store_manager, mmr, _) = initialize_mmr(store_path).await?;
+        let options = SqliteConnectOptions::new()
+            .filename(store_path)
+            .read_only(true);
+        let pool = SqlitePool::connect_with(options).await?;
+
+        let mut builder = Self::with_store(store_manager, mmr, pool, proof_generator, 1);
+        match load_last_checkpoint(&builder.pool).await {
+            Ok(Some(checkpoint)) => {
+                builder.hasher = checkpoint.hasher;
+                if !builder.matches_checkpoint(&checkpoint).await? {
+                    warn!(
+                        "The MMR store does not match its last checkpoint, of blocks {} to {}: a batch was partially applied",
+                        checkpoint.start_block, checkpoint.end_block
+                    );
+                }
+            }
+            Ok(None) => {}
+            Err(e) => warn!(
+                "Failed to read the last checkpoint, the store may need migrating: {}",
+                e
+            ),
+        }
+
+        Ok(builder)
+    }
+
+    fn with_store(
+        store_manager: StoreManager,
+        mmr: MMR,
+        pool: SqlitePool,
+        proof_generator: ProofGenerator,
+        batch_size: u64,
+    ) -> Self {
+        Self {
             batch_size,
             store_manager,
             mmr,
@@ -41,17 +150,306 @@ impl AccumulatorBuilder {
             proof_generator,
             total_batches: 0,
             current_batch: 0,
-            previous_proofs: Vec::new(),
-        })
+            previous_proof: None,
+            previous_receipt: None,
+            aggregation_method_id: MMR_AGGREGATE_ID,
+            journal_format: JournalFormat::Full,
+            hasher: HasherKind::default(),
+            batch_size_tuner: None,
+            parallel_proving: None,
+            artifacts: None,
+            header_source: Arc::new(RpcHeaderSource),
+        }
     }
 
-    async fn process_batch(&mut self, start_block: u64, end_block: u64) -> Result<BatchResult> {
-        // Fetch headers
-        let headers = get_block_headers_in_range(start_block, end_block).await?;
-        debug!("Fetched {} headers", headers.len());
+    /// Set the format of the journals committed by the next batches. Batches
+    /// already proven keep their own format.
+    pub fn with_journal_format(mut self, journal_format: JournalFormat) -> Self {
+        self.journal_format = journal_format;
+        self
+    }
+
+    /// Pick the batch size from the cycles per header measured by executing a
+    /// sample of the first batch, and keep adjusting it between batches.
+    pub fn with_batch_size_tuning(mut self, tuner: BatchSizeTuner) -> Self {
+        self.batch_size_tuner = Some(tuner);
+        self
+    }
+
+    /// Prove the batches of a build concurrently on `workers` workers instead
+    /// of chaining each into the next, and prove the whole build with a single
+    /// Groth16 proof of `aggregation_prover`, which must run the aggregation
+    /// guest of `methods`. Later batches chain from that proof.
+    pub fn with_parallel_proving(
+        mut self,
+        workers: usize,
+        aggregation_prover: ProofGenerator,
+    ) -> Self {
+        self.parallel_proving = Some(ParallelProving {
+            workers,
+            aggregation_prover,
+        });
+        self
+    }
+
+    /// Write the proof of every batch to `artifacts` as soon as it is applied.
+    pub fn with_artifacts(mut self, artifacts: ArtifactStore) -> Self {
+        self.artifacts = Some(artifacts);
+        self
+    }
+
+    /// Read block headers, and the finalized block, from `header_source`
+    /// instead of the indexer database and the Ethereum RPC.
+    pub fn with_header_source(mut self, header_source: Arc<dyn HeaderSource>) -> Self {
+        self.header_source = header_source;
+        self
+    }
+
+    /// Set the hasher of the MMR. An existing MMR keeps the hasher it was
+    /// built with, so this fails if the store is not empty and `hasher`
+    /// differs from it.
+    pub async fn with_hasher(mut self, hasher: HasherKind) -> Result<Self> {
+        if hasher != self.hasher && self.mmr.elements_count.get().await? > 0 {
+            return Err(eyre::eyre!(
+                "The MMR store was built with the {} hasher, not {}",
+                self.hasher,
+                hasher
+            ));
+        }
+        self.hasher = hasher;
+        Ok(self)
+    }
+
+    /// Restore the state of the last completed batch, rolling back any batch
+    /// that was only partially written to the MMR store, and the segments of
+    /// a parallel build that was not checkpointed as a whole.
+    async fn resume_from_checkpoint(&mut self) -> Result<()> {
+        self.roll_back_interrupted_build().await?;
+
+        let Some(checkpoint) = load_last_checkpoint(&self.pool).await? else {
+            // Nothing to roll back to: a store written without checkpoints
+            // is taken as it is, but cannot be chained into
+            let stored_elements_count = self.mmr.elements_count.get().await?;
+            if stored_elements_count > 0 {
+                warn!(
+                    "The MMR store holds {} elements but no checkpoint, the next batch will not be chained to a proof of them",
+                    stored_elements_count
+                );
+            }
+            return Ok(());
+        };
+
+        if !self.matches_checkpoint(&checkpoint).await? {
+            warn!(
+                "Rolling back partially applied batch: {} elements stored, {} checkpointed",
+                self.mmr.elements_count.get().await?,
+                checkpoint.elements_count
+            );
+
+            let mut tx = self.pool.begin().await?;
+            if let Err(e) = self
+                .roll_back_to(
+                    &mut tx,
+                    checkpoint.elements_count,
+                    checkpoint.leaves_count,
+                    &checkpoint.peaks,
+                )
+                .await
+            {
+                tx.rollback().await?;
+                return Err(e);
+            }
+            tx.commit().await?;
+        }
 
+        info!(
+            "Resuming from checkpoint for blocks {} to {}",
+            checkpoint.start_block, checkpoint.end_block
+        );
+        self.hasher = checkpoint.hasher;
+
+        // STARK receipts, or the succinct receipt of a Groth16 proof, are
+        // chained into later batches, as are unproven batches without a
+        // receipt. The last checkpoint of a parallel build is its aggregation.
+        match checkpoint.proof {
+            ProofType::Stark {
+                receipt, method_id, ..
+            } => {
+                self.previous_proof = Some(BatchProof {
+                    journal: checkpoint.journal,
+                    method_id,
+                    format: checkpoint.journal_format,
+                    kind: checkpoint.proof_kind,
+                });
+                self.previous_receipt = Some(receipt);
+            }
+            ProofType::Unproven { method_id, .. } => {
+                self.previous_proof = Some(BatchProof {
+                    journal: checkpoint.journal,
+                    method_id,
+                    format: checkpoint.journal_format,
+                    kind: checkpoint.proof_kind,
+                });
+            }
+            ProofType::Groth16 { .. } => match checkpoint.chain_receipt {
+                Some(receipt) => {
+                    let method_id = match checkpoint.proof_kind {
+                        ProofKind::Batch => self.proof_generator.method_id(),
+                        ProofKind::Aggregation => self.aggregation_method_id,
+                    };
+                    self.previous_proof = Some(BatchProof {
+                        journal: checkpoint.journal,
+                        method_id,
+                        format: checkpoint.journal_format,
+                        kind: checkpoint.proof_kind,
+                    });
+                    self.previous_receipt = Some(receipt);
+                }
+                None => warn!(
+                    "The last batch was checkpointed without its succinct receipt, the next batch will not be chained to it"
+                ),
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Whether the stored counters and peaks are those of `checkpoint`.
+    async fn matches_checkpoint(&self, checkpoint: &BatchCheckpoint) -> Result<bool> {
+        // Peaks may be missing if the crash happened while writing hashes
+        let stored_peaks = self.get_peaks().await.ok();
+        Ok(
+            self.mmr.elements_count.get().await? == checkpoint.elements_count
+                && self.mmr.leaves_count.get().await? == checkpoint.leaves_count
+                && stored_peaks.as_ref() == Some(&checkpoint.peaks),
+        )
+    }
+
+    /// The segments of a parallel build only continue the MMR through the
+    /// aggregation proof checkpointed after them. Without it, roll the MMR
+    /// back to the last checkpoint later batches can chain from, or to the
+    /// empty MMR if there is none, and drop the checkpoints of the segments.
+    async fn roll_back_interrupted_build(&self) -> Result<()> {
+        match load_last_checkpoint(&self.pool).await? {
+            Some(checkpoint) if !checkpoint.chainable => {}
+            _ => return Ok(()),
+        }
+        let (elements_count, leaves_count, peaks) =
+            match load_last_chainable_checkpoint(&self.pool).await? {
+                Some(checkpoint) => (
+                    checkpoint.elements_count,
+                    checkpoint.leaves_count,
+                    checkpoint.peaks,
+                ),
+                None => (0, 0, Vec::new()),
+            };
+        warn!(
+            "Rolling back a parallel build that was not aggregated, to {} elements",
+            elements_count
+        );
+
+        let mut tx = self.pool.begin().await?;
+        let rolled_back = async {
+            delete_unchainable_checkpoints(&mut tx).await?;
+            self.roll_back_to(&mut tx, elements_count, leaves_count, &peaks)
+                .await
+        }
+        .await;
+        if let Err(e) = rolled_back {
+            tx.rollback().await?;
+            return Err(e);
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Reset the MMR counters and peaks to the given state, and drop the
+    /// block index entries of any later element.
+    async fn roll_back_to(
+        &self,
+        conn: &mut SqliteConnection,
+        elements_count: usize,
+        leaves_count: usize,
+        peaks: &[Felt],
+    ) -> Result<()> {
+        set_store_value(
+            conn,
+            &self.mmr.elements_count.key,
+            &elements_count.to_string(),
+        )
+        .await?;
+        set_store_value(conn, &self.mmr.leaves_count.key, &leaves_count.to_string()).await?;
+        for (peak_hash, peak_idx) in peaks.iter().zip(find_peaks(elements_count)) {
+            set_store_value(conn, &self.hash_key(peak_idx), &felt::to_hex(peak_hash)).await?;
+        }
+
+        sqlx::query("DELETE FROM block_leaf_index WHERE element_index > ?")
+            .bind(elements_count as i64)
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Assumptions for the guest's verification of the previous batch when
+    /// only executing it: its receipt if it was proven, else its claim,
+    /// left unresolved.
+    fn previous_assumptions(&self) -> Vec<AssumptionReceipt> {
+        match (&self.previous_receipt, &self.previous_proof) {
+            (Some(receipt), _) => vec![receipt.clone().into()],
+            (None, Some(proof)) => {
+                vec![ReceiptClaim::ok(proof.method_id, proof.journal.clone()).into()]
+            }
+            (None, None) => Vec::new(),
+        }
+    }
+
+    /// Execute the guest over `first_block..=last_block` without recording
+    /// anything, and size the batches from the measured cycles.
+    async fn calibrate_batch_size(
+        &mut self,
+        first_block: u64,
+        last_block: u64,
+        direction: Direction,
+    ) -> Result<()> {
+        if self.batch_size_tuner.is_none() {
+            return Ok(());
+        }
+
+        let headers = self
+            .fetch_headers(first_block, last_block, direction)
+            .await?;
+        let header_count = headers.len();
+        let new_elements = block_elements(self.hasher, &headers)?;
+        let mmr_input = self.current_guest_input(new_elements).await?;
+        let batch = self.prepare_batch(first_block, last_block, headers, mmr_input)?;
+        let sample = self
+            .proof_generator
+            .execute(
+                &batch.input,
+                self.previous_assumptions(),
+                self.side_channel(),
+            )
+            .await?;
+
+        if let Some(tuner) = &mut self.batch_size_tuner {
+            tuner.observe(header_count, &sample.stats, self.batch_size);
+            if let Some(batch_size) = tuner.batch_size() {
+                info!(
+                    "Sampled {} cycles per header, using batches of {} blocks",
+                    sample.stats.user_cycles / header_count.max(1) as u64,
+                    batch_size
+                );
+                self.batch_size = batch_size;
+            }
+        }
+        Ok(())
+    }
+
+    /// Guest input appending `new_elements` to the current MMR state,
+    /// continuing the previous batch proof.
+    async fn current_guest_input(&self, new_elements: Vec<Felt>) -> Result<GuestInput> {
         // Get and verify current MMR state
-        let current_peaks = self.mmr.get_peaks(PeaksOptions::default()).await?;
+        let current_peaks = self.get_peaks().await?;
         let current_elements_count = self.mmr.elements_count.get().await?;
         let current_leaves_count = self.mmr.leaves_count.get().await?;
 
@@ -60,67 +458,564 @@ impl AccumulatorBuilder {
         debug!("  Leaves count: {}", current_leaves_count);
         debug!("  Peaks: {:?}", current_peaks);
 
-        // Prepare guest input
-        let mmr_input = GuestInput {
-            initial_peaks: current_peaks.clone(),
+        Ok(GuestInput {
+            initial_peaks: current_peaks,
             elements_count: current_elements_count,
             leaves_count: current_leaves_count,
-            new_elements: headers.iter().map(|h| h.block_hash.clone()).collect(),
-            previous_proofs: self.previous_proofs.clone(), // Use the stored proofs
+            new_elements,
+            previous_proofs: self.previous_proof.clone(), // Only the latest batch is needed
+            method_id: self.proof_generator.method_id(),
+            aggregation_method_id: self.aggregation_method_id,
+        })
+    }
+
+    /// Build the guest input of a batch and replay it with the guest's own MMR
+    /// code, so that a guest output diverging from the host can be caught
+    /// before touching the store.
+    ///
+    /// A batch without headers appends generic leaves, `start_block` and
+    /// `end_block` then being the positions of its first and last leaf.
+    fn prepare_batch(
+        &self,
+        start_block: u64,
+        end_block: u64,
+        fetched: Vec<FetchedHeader>,
+        mmr_input: GuestInput,
+    ) -> Result<PreparedBatch> {
+        let (headers, requests_hashes): (Vec<_>, Vec<_>) = fetched
+            .into_iter()
+            .map(|fetched| (fetched.header, fetched.requests_hash))
+            .unzip();
+        let (mode, block_range) = if headers.is_empty() {
+            (AppendMode::GenericLeaves, None)
+        } else {
+            (AppendMode::BlockHashes, Some((start_block, end_block)))
         };
+        let expected_output = append_batch(self.hasher, mmr_input.clone(), block_range)
+            .map_err(|e| eyre::eyre!("Failed to replay batch: {}", e))?;
 
-        let combined_input = CombinedInput {
+        let input = CombinedInput {
+            mode,
             headers: headers.clone(),
+            requests_hashes,
+            journal_format: self.journal_format,
+            hasher: self.hasher,
             mmr_input,
         };
 
-        // Generate appropriate proof
-        let proof = if self.current_batch == self.total_batches - 1 {
+        Ok(PreparedBatch {
+            start_block,
+            end_block,
+            headers,
+            input,
+            expected_output,
+        })
+    }
+
+    /// A compact journal leaves the append results to the guest's stdout.
+    fn side_channel(&self) -> Option<SideChannel> {
+        match self.journal_format {
+            JournalFormat::Full => None,
+            JournalFormat::Compact => Some(SideChannel::default()),
+        }
+    }
+
+    /// Headers of `start_block..=end_block` in the append order of
+    /// `direction`.
+    async fn fetch_headers(
+        &self,
+        start_block: u64,
+        end_block: u64,
+        direction: Direction,
+    ) -> Result<Vec<FetchedHeader>> {
+        // Sources return every block of the range once, in ascending order
+        let mut headers = self
+            .header_source
+            .headers_in_range(start_block, end_block)
+            .await?;
+        if direction == Direction::Backward {
+            headers.reverse();
+        }
+        Ok(headers)
+    }
+
+    async fn process_batch(
+        &mut self,
+        start_block: u64,
+        end_block: u64,
+        direction: Direction,
+    ) -> Result<BatchResult> {
+        // Fetch headers
+        let headers = self
+            .fetch_headers(start_block, end_block, direction)
+            .await?;
+        debug!("Fetched {} headers", headers.len());
+
+        let new_elements = block_elements(self.hasher, &headers)?;
+        let mmr_input = self.current_guest_input(new_elements).await?;
+        let batch = self.prepare_batch(start_block, end_block, headers, mmr_input)?;
+        self.prove_batch(batch).await
+    }
+
+    /// Append `leaves` as a single batch of generic leaves, the first one at
+    /// leaf position `first_leaf`.
+    async fn process_leaves_batch(
+        &mut self,
+        first_leaf: u64,
+        leaves: Vec<Felt>,
+    ) -> Result<BatchResult> {
+        let last_leaf = first_leaf + leaves.len() as u64 - 1;
+        let mmr_input = self.current_guest_input(leaves).await?;
+        let batch = self.prepare_batch(first_leaf, last_leaf, Vec::new(), mmr_input)?;
+        self.prove_batch(batch).await
+    }
+
+    /// Prove, or only execute, a prepared batch and apply it to the store.
+    async fn prove_batch(&mut self, batch: PreparedBatch) -> Result<BatchResult> {
+        let side_channel = self.side_channel();
+
+        // Generate appropriate proof, or only execute the guest
+        let assumptions = self.previous_receipt.as_slice();
+        let generated = if self.proof_generator.is_execute_only() {
             self.proof_generator
-                .generate_groth16_proof(&combined_input)
+                .execute(
+                    &batch.input,
+                    self.previous_assumptions(),
+                    side_channel.clone(),
+                )
+                .await?
+        } else if self.previous_proof.is_some() && self.previous_receipt.is_none() {
+            return Err(eyre::eyre!(
+                "The previous batch was only executed, it cannot be chained into a proven batch"
+            ));
+        } else if self.current_batch + 1 == self.total_batches {
+            self.proof_generator
+                .generate_groth16_proof(&batch.input, assumptions, side_channel.clone())
                 .await?
         } else {
             self.proof_generator
-                .generate_stark_proof(&combined_input)
+                .generate_stark_proof(&batch.input, assumptions, side_channel.clone())
                 .await?
         };
 
+        let result = self
+            .apply_batch(&batch, generated, side_channel, true)
+            .await?;
+
+        // Follow drifts in cycles per header, e.g. larger headers in newer blocks
+        let element_count = batch.input.mmr_input.new_elements.len();
+        if let (Some(tuner), Some(stats)) = (&mut self.batch_size_tuner, &result.stats) {
+            if let Some(batch_size) = tuner.observe(element_count, stats, self.batch_size) {
+                info!(
+                    "Adjusting batch size from {} to {} blocks",
+                    self.batch_size, batch_size
+                );
+                self.batch_size = batch_size;
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Check the output of a proven batch and write it to the store, then
+    /// chain its proof into the next batch if it is `chainable`.
+    async fn apply_batch(
+        &mut self,
+        batch: &PreparedBatch,
+        generated: GeneratedProof,
+        side_channel: Option<SideChannel>,
+        chainable: bool,
+    ) -> Result<BatchResult> {
+        let GeneratedProof {
+            proof,
+            stats,
+            succinct_receipt,
+        } = generated;
+        let mmr_input = &batch.input.mmr_input;
+        let expected_output = &batch.expected_output;
+
         // Decode and update state
-        let guest_output: GuestOutput = self.proof_generator.decode_journal(&proof)?;
-        self.update_mmr_state(&guest_output).await?;
-
-        // If this is a STARK proof, add it to previous_proofs for the next batch
-        if let ProofType::Stark {
-            ref receipt,
-            ref image_id,
-            method_id,
-        } = proof
+        let (guest_output, side_output): (GuestOutput, _) = match side_channel {
+            None => (self.proof_generator.decode_journal(&proof)?, None),
+            Some(side_channel) => {
+                let journal: CompactGuestOutput = self.proof_generator.decode_journal(&proof)?;
+                let side_output: BatchSideOutput = side_channel.decode()?;
+                (journal.expand(side_output.clone())?, Some(side_output))
+            }
+        };
+
+        // The proof must be bound to the state we started from
+        if guest_output.initial_peaks != mmr_input.initial_peaks
+            || guest_output.initial_elements_count != mmr_input.elements_count
+            || guest_output.initial_leaves_count != mmr_input.leaves_count
+            || guest_output.method_id != mmr_input.method_id
         {
-            self.previous_proofs.push(BatchProof {
-                receipt: receipt.clone(),
-                image_id: image_id.clone(),
-                method_id,
-            });
+            return Err(eyre::eyre!(
+                "Guest output does not start from the current MMR state"
+            ));
+        }
+        if guest_output.final_peaks != expected_output.final_peaks
+            || guest_output.elements_count != expected_output.elements_count
+            || guest_output.leaves_count != expected_output.leaves_count
+        {
+            return Err(eyre::eyre!(
+                "Guest output does not match the replayed batch"
+            ));
+        }
+        if matches!(proof, ProofType::Groth16 { .. }) && succinct_receipt.is_none() {
+            return Err(eyre::eyre!(
+                "Groth16 proof generated without the succinct receipt to chain from"
+            ));
+        }
+        if !guest_output.chained {
+            warn!(
+                "Blocks {} to {} were proven without a proof of the MMR before them",
+                batch.start_block, batch.end_block
+            );
         }
 
+        let block_numbers: Vec<u64> = batch.headers.iter().map(|h| h.number as u64).collect();
+        let checkpoint = BatchCheckpoint {
+            start_block: batch.start_block,
+            end_block: batch.end_block,
+            journal: proof.journal().to_vec(),
+            proof: proof.clone(),
+            peaks: guest_output.final_peaks.clone(),
+            elements_count: guest_output.elements_count,
+            leaves_count: guest_output.leaves_count,
+            journal_format: self.journal_format,
+            hasher: self.hasher,
+            stats: Some(stats),
+            chain_receipt: succinct_receipt.clone(),
+            chainable,
+            proof_kind: ProofKind::Batch,
+        };
+        self.update_mmr_state(
+            &guest_output,
+            &mmr_input.new_elements,
+            &block_numbers,
+            &checkpoint,
+        )
+        .await?;
+
         // Verify state after update
-        let final_peaks = self.mmr.get_peaks(PeaksOptions::default()).await?;
+        let final_peaks = self.get_peaks().await?;
         if final_peaks != guest_output.final_peaks {
             return Err(eyre::eyre!(
                 "Final peaks verification failed after batch processing"
             ));
         }
 
+        // If this is a STARK proof, it becomes the previous proof for the next batch,
+        // as does the succinct receipt of a Groth16 proof. An unproven batch is
+        // chained the same way, without a receipt. Nothing is chained from a
+        // segment of a parallel build, only from its aggregation.
+        match proof {
+            ProofType::Stark {
+                ref receipt,
+                method_id,
+                ..
+            } => {
+                self.previous_proof = Some(BatchProof {
+                    journal: receipt.journal.bytes.clone(),
+                    method_id,
+                    format: self.journal_format,
+                    kind: ProofKind::Batch,
+                });
+                self.previous_receipt = Some(receipt.clone());
+            }
+            ProofType::Unproven {
+                ref journal,
+                method_id,
+                ..
+            } => {
+                self.previous_proof = Some(BatchProof {
+                    journal: journal.clone(),
+                    method_id,
+                    format: self.journal_format,
+                    kind: ProofKind::Batch,
+                });
+                self.previous_receipt = None;
+            }
+            ProofType::Groth16 { .. } => {
+                if let Some(receipt) = succinct_receipt {
+                    self.previous_proof = Some(BatchProof {
+                        journal: receipt.journal.bytes.clone(),
+                        method_id: self.proof_generator.method_id(),
+                        format: self.journal_format,
+                        kind: ProofKind::Batch,
+                    });
+                    self.previous_receipt = Some(receipt);
+                }
+            }
+        }
+        if !chainable {
+            self.previous_proof = None;
+            self.previous_receipt = None;
+        }
+
         self.current_batch += 1;
 
-        Ok(BatchResult {
+        let result = BatchResult {
+            start_block: batch.start_block,
+            end_block: batch.end_block,
+            proof: Some(proof),
+            stats: Some(stats),
+            image_id: self.proof_generator.method_id(),
+            side_output,
+        };
+        self.save_artifact(&result)?;
+        Ok(result)
+    }
+
+    fn save_artifact(&mut self, result: &BatchResult) -> Result<()> {
+        match &mut self.artifacts {
+            Some(artifacts) => artifacts.save(result),
+            None => Ok(()),
+        }
+    }
+
+    /// Prove `ranges`, in append order, concurrently on the worker pool. Each
+    /// batch is proven on its own from the state the batches before it end
+    /// in, as replayed on the host, and the aggregation guest then checks
+    /// that they connect and proves the whole run with a single Groth16
+    /// proof, returned as the last result.
+    ///
+    /// The segments are not chained to each other, so they are only written
+    /// to the store once the aggregation is proven, and only its proof is
+    /// chained into later batches. A build interrupted while writing them is
+    /// rolled back by `resume_from_checkpoint`.
+    async fn process_batches_parallel(
+        &mut self,
+        ranges: Vec<(u64, u64)>,
+        direction: Direction,
+    ) -> Result<Vec<BatchResult>> {
+        let Some(parallel) = self.parallel_proving.clone() else {
+            return Err(eyre::eyre!("Parallel proving is not configured"));
+        };
+        if ranges.is_empty() {
+            return Ok(Vec::new());
+        }
+        if parallel.aggregation_prover.method_id() != self.aggregation_method_id {
+            return Err(eyre::eyre!(
+                "The aggregation prover does not run the aggregation guest later batches chain from"
+            ));
+        }
+        if !self.proof_generator.is_execute_only()
+            && self.previous_proof.is_some()
+            && self.previous_receipt.is_none()
+        {
+            return Err(eyre::eyre!(
+                "The previous batch was only executed, it cannot be chained into a proven batch"
+            ));
+        }
+        self.total_batches = ranges.len() as u64;
+        self.current_batch = 0;
+
+        // Replay every batch on the host to find the state each one starts from
+        let mut peaks = self.get_peaks().await?;
+        let mut elements_count = self.mmr.elements_count.get().await?;
+        let mut leaves_count = self.mmr.leaves_count.get().await?;
+        let mut batches = Vec::with_capacity(ranges.len());
+        for (start_block, end_block) in ranges {
+            let headers = self
+                .fetch_headers(start_block, end_block, direction)
+                .await?;
+            let mmr_input = GuestInput {
+                initial_peaks: peaks,
+                elements_count,
+                leaves_count,
+                new_elements: block_elements(self.hasher, &headers)?,
+                previous_proofs: None,
+                method_id: self.proof_generator.method_id(),
+                aggregation_method_id: self.aggregation_method_id,
+            };
+            let batch = self.prepare_batch(start_block, end_block, headers, mmr_input)?;
+            peaks = batch.expected_output.final_peaks.clone();
+            elements_count = batch.expected_output.elements_count;
+            leaves_count = batch.expected_output.leaves_count;
+            batches.push(batch);
+        }
+
+        // Prove the batches, at most `workers` at a time
+        info!(
+            "Proving {} batches on {} workers",
+            batches.len(),
+            parallel.workers
+        );
+        let workers = Arc::new(Semaphore::new(parallel.workers.max(1)));
+        let mut tasks = JoinSet::new();
+        for (index, batch) in batches.iter().enumerate() {
+            let workers = workers.clone();
+            let proof_generator = self.proof_generator.clone();
+            let input = batch.input.clone();
+            let side_channel = self.side_channel();
+            tasks.spawn(async move {
+                let _worker = workers.acquire_owned().await?;
+                let generated = if proof_generator.is_execute_only() {
+                    proof_generator
+                        .execute(&input, Vec::new(), side_channel.clone())
+                        .await?
+                } else {
+                    proof_generator
+                        .generate_stark_proof(&input, &[], side_channel.clone())
+                        .await?
+                };
+                Ok::<_, eyre::Report>((index, generated, side_channel))
+            });
+        }
+        let mut generated = Vec::with_capacity(batches.len());
+        while let Some(task) = tasks.join_next().await {
+            generated.push(task??);
+        }
+        generated.sort_by_key(|(index, _, _)| *index);
+
+        // Aggregate the batches before writing any of them, as they only
+        // continue the MMR through the aggregation proof
+        let batch_proofs = generated
+            .iter()
+            .map(|(_, generated, _)| BatchProof {
+                journal: generated.proof.journal().to_vec(),
+                method_id: self.proof_generator.method_id(),
+                format: self.journal_format,
+                kind: ProofKind::Batch,
+            })
+            .collect::<Vec<_>>();
+        info!("Aggregating {} batch proofs", batch_proofs.len());
+        let input = AggregationInput {
+            batch_method_id: self.proof_generator.method_id(),
+            previous_proof: self.previous_proof.clone(),
+            batches: batch_proofs,
+        };
+        let aggregated = if self.proof_generator.is_execute_only() {
+            let mut assumptions = self.previous_assumptions();
+            assumptions.extend(input.batches.iter().map(|batch| {
+                AssumptionReceipt::from(ReceiptClaim::ok(batch.method_id, batch.journal.clone()))
+            }));
+            parallel
+                .aggregation_prover
+                .execute(&input, assumptions, None)
+                .await?
+        } else {
+            let mut assumptions: Vec<Receipt> = self.previous_receipt.iter().cloned().collect();
+            for (_, generated, _) in &generated {
+                if let ProofType::Stark { receipt, .. } = &generated.proof {
+                    assumptions.push(receipt.clone());
+                }
+            }
+            parallel
+                .aggregation_prover
+                .generate_groth16_proof(&input, &assumptions, None)
+                .await?
+        };
+
+        let output: AggregationOutput = parallel
+            .aggregation_prover
+            .decode_journal(&aggregated.proof)?;
+        let Some((start_block, end_block)) = output.transition.block_range else {
+            return Err(eyre::eyre!("Aggregated batches have no block range"));
+        };
+        let last = &batches[batches.len() - 1].expected_output;
+        if !output
+            .transition
+            .ends_at(&last.final_peaks, last.elements_count, last.leaves_count)
+        {
+            return Err(eyre::eyre!(
+                "Aggregated batches do not end in the replayed state"
+            ));
+        }
+        if matches!(aggregated.proof, ProofType::Groth16 { .. })
+            && aggregated.succinct_receipt.is_none()
+        {
+            return Err(eyre::eyre!(
+                "Groth16 proof generated without the succinct receipt to chain from"
+            ));
+        }
+
+        // Apply the segments in append order, then checkpoint the aggregation
+        // they are chained through. Should any step fail, the segments
+        // already written are rolled back.
+        let applied = self
+            .apply_parallel_build(&batches, generated, &aggregated, start_block, end_block)
+            .await;
+        let mut results = match applied {
+            Ok(results) => results,
+            Err(e) => {
+                self.resume_from_checkpoint().await?;
+                return Err(e);
+            }
+        };
+
+        self.previous_proof = Some(BatchProof {
+            journal: aggregated.proof.journal().to_vec(),
+            method_id: self.aggregation_method_id,
+            format: JournalFormat::Full,
+            kind: ProofKind::Aggregation,
+        });
+        self.previous_receipt = aggregated.succinct_receipt;
+
+        let result = BatchResult {
             start_block,
             end_block,
-            proof: Some(proof),
-        })
+            proof: Some(aggregated.proof),
+            stats: Some(aggregated.stats),
+            image_id: self.aggregation_method_id,
+            side_output: None,
+        };
+        self.save_artifact(&result)?;
+        results.push(result);
+        Ok(results)
     }
 
-    async fn update_mmr_state(&mut self, guest_output: &GuestOutput) -> Result<()> {
+    /// Write the segments of a parallel build to the store, each in its own
+    /// transaction, followed by the checkpoint of their aggregation.
+    async fn apply_parallel_build(
+        &mut self,
+        batches: &[PreparedBatch],
+        generated: Vec<(usize, GeneratedProof, Option<SideChannel>)>,
+        aggregated: &GeneratedProof,
+        start_block: u64,
+        end_block: u64,
+    ) -> Result<Vec<BatchResult>> {
+        let mut results = Vec::with_capacity(batches.len() + 1);
+        for (batch, (_, generated, side_channel)) in batches.iter().zip(generated) {
+            results.push(
+                self.apply_batch(batch, generated, side_channel, false)
+                    .await?,
+            );
+        }
+
+        let checkpoint = BatchCheckpoint {
+            start_block,
+            end_block,
+            journal: aggregated.proof.journal().to_vec(),
+            proof: aggregated.proof.clone(),
+            peaks: self.get_peaks().await?,
+            elements_count: self.mmr.elements_count.get().await?,
+            leaves_count: self.mmr.leaves_count.get().await?,
+            journal_format: JournalFormat::Full,
+            hasher: self.hasher,
+            stats: Some(aggregated.stats),
+            chain_receipt: aggregated.succinct_receipt.clone(),
+            chainable: true,
+            proof_kind: ProofKind::Aggregation,
+        };
+        let mut tx = self.pool.begin().await?;
+        save_checkpoint(&mut tx, &checkpoint).await?;
+        tx.commit().await?;
+        Ok(results)
+    }
+
+    /// Apply a batch to the MMR store in a single transaction, together with
+    /// its block index and checkpoint. Nothing is written if any step fails or
+    /// if the stored peaks do not match the guest output.
+    async fn update_mmr_state(
+        &mut self,
+        guest_output: &GuestOutput,
+        new_elements: &[Felt],
+        block_numbers: &[u64],
+        checkpoint: &BatchCheckpoint,
+    ) -> Result<()> {
         debug!("Guest output: {:?}", guest_output);
         // Verify state transition
         let current_elements_count = self.mmr.elements_count.get().await?;
@@ -130,67 +1025,525 @@ impl AccumulatorBuilder {
             ));
         }
 
+        if new_elements.len() != guest_output.append_results.len() {
+            return Err(eyre::eyre!(
+                "Invalid state transition: {} elements appended, {} expected",
+                guest_output.append_results.len(),
+                new_elements.len()
+            ));
+        }
+
+        check_new_nodes(guest_output, current_elements_count)?;
+
         debug!("Updating MMR state:");
         debug!("  Current elements count: {}", current_elements_count);
         debug!("  New elements count: {}", guest_output.elements_count);
         debug!("  New peaks: {:?}", guest_output.final_peaks);
 
-        // First update the MMR counters
-        self.mmr
-            .elements_count
-            .set(guest_output.elements_count)
+        let mut tx = self.pool.begin().await?;
+        if let Err(e) = self
+            .write_mmr_state(&mut tx, guest_output, block_numbers, checkpoint)
+            .await
+        {
+            tx.rollback().await?;
+            return Err(e);
+        }
+        tx.commit().await?;
+
+        // Map the appended elements to their leaf index. The mapping is
+        // idempotent and not part of the MMR state, so it is kept out of the
+        // transaction.
+        for (result, element) in guest_output.append_results.iter().zip(new_elements) {
+            self.store_manager
+                .insert_value_index_mapping(
+                    &self.pool,
+                    &felt::to_hex(element),
+                    result.element_index,
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn write_mmr_state(
+        &self,
+        conn: &mut SqliteConnection,
+        guest_output: &GuestOutput,
+        block_numbers: &[u64],
+        checkpoint: &BatchCheckpoint,
+    ) -> Result<()> {
+        // Store every node created by the batch, leaves and parents, so that
+        // any element can later be proven from the store alone
+        for node in &guest_output.new_nodes {
+            debug!("  Storing hash at index {}: {}", node.index, node.hash);
+            set_store_value(conn, &self.hash_key(node.index), &felt::to_hex(&node.hash)).await?;
+        }
+
+        // Update the MMR counters
+        set_store_value(
+            conn,
+            &self.mmr.elements_count.key,
+            &guest_output.elements_count.to_string(),
+        )
+        .await?;
+        set_store_value(
+            conn,
+            &self.mmr.leaves_count.key,
+            &guest_output.leaves_count.to_string(),
+        )
+        .await?;
+
+        // Record where each block landed in the MMR
+        for (result, &block_number) in guest_output.append_results.iter().zip(block_numbers) {
+            sqlx::query(
+                "INSERT OR REPLACE INTO block_leaf_index (block_number, leaf_index, element_index) VALUES (?, ?, ?)",
+            )
+            .bind(block_number as i64)
+            .bind((result.leaves_count - 1) as i64)
+            .bind(result.element_index as i64)
+            .execute(&mut *conn)
             .await?;
-        self.mmr.leaves_count.set(guest_output.leaves_count).await?;
+        }
 
-        // Update all hashes in the store
-        for result in &guest_output.append_results {
-            debug!(
-                "  Storing hash at index {}: {}",
-                result.element_index, result.root_hash
-            );
+        // The peaks are among the stored nodes, verify them before committing
+        let peaks_indices = find_peaks(guest_output.elements_count);
+        let mut stored_peaks = Vec::new();
+        for &peak_idx in &peaks_indices {
+            let peak = get_store_value(conn, &self.hash_key(peak_idx))
+                .await?
+                .ok_or_else(|| eyre::eyre!("No hash stored at index {}", peak_idx))?;
+            stored_peaks.push(parse_felt(&peak)?);
+        }
+        debug!("Verified stored peaks: {:?}", stored_peaks);
+
+        if stored_peaks != guest_output.final_peaks {
+            return Err(eyre::eyre!("Failed to verify stored peaks after update"));
+        }
+
+        save_checkpoint(conn, checkpoint).await
+    }
+
+    /// Key of the hash at `element_index`, following the layout of the MMR's
+    /// `hashes` table in the store.
+    fn hash_key(&self, element_index: usize) -> String {
+        format!("{}:{}", self.mmr.hashes.key, element_index)
+    }
+
+    /// Build an inclusion proof for the hash of `block_number` against the
+    /// current MMR state, from the hashes stored so far.
+    pub async fn get_inclusion_proof(&self, block_number: u64) -> Result<Proof> {
+        let element_index = self
+            .get_block_element_index(block_number)
+            .await?
+            .ok_or_else(|| eyre::eyre!("Block {} is not in the MMR", block_number))?;
+
+        let elements_count = self.mmr.elements_count.get().await?;
+        let element_hash = self.get_stored_hash(element_index).await?;
+
+        let mut siblings_hashes = Vec::new();
+        for sibling_index in find_siblings(element_index, elements_count)? {
+            siblings_hashes.push(self.get_stored_hash(sibling_index).await?);
+        }
+
+        let peaks_hashes = self.get_peaks().await?;
+
+        Ok(Proof {
+            element_index,
+            element_hash,
+            siblings_hashes,
+            peaks_hashes,
+            elements_count,
+            hasher: self.hasher,
+        })
+    }
 
-            // Store the hash in MMR
-            self.mmr
-                .hashes
-                .set(&result.root_hash, SubKey::Usize(result.element_index))
+    /// Prove with the inclusion guest that `block_number` is committed in the
+    /// current MMR root. `inclusion_prover` must wrap the inclusion guest.
+    pub async fn prove_block_inclusion(
+        &self,
+        block_number: u64,
+        inclusion_prover: &ProofGenerator,
+    ) -> Result<ProofType> {
+        let fetched = self
+            .header_source
+            .headers_in_range(block_number, block_number)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| eyre::eyre!("Block {} not found", block_number))?;
+        let proof = self.get_inclusion_proof(block_number).await?;
+        let root = calculate_root_hash(proof.hasher, &proof.peaks_hashes, proof.elements_count);
+
+        let input = InclusionInput {
+            header: fetched.header,
+            requests_hash: fetched.requests_hash,
+            proof,
+            root,
+        };
+
+        inclusion_prover
+            .generate_groth16_proof(&input, &[], None)
+            .await
+            .map(|generated| generated.proof)
+    }
+
+    /// Element index of the leaf holding the hash of `block_number`, if accumulated.
+    pub async fn get_block_element_index(&self, block_number: u64) -> Result<Option<usize>> {
+        let element_index: Option<i64> =
+            sqlx::query_scalar("SELECT element_index FROM block_leaf_index WHERE block_number = ?")
+                .bind(block_number as i64)
+                .fetch_optional(&self.pool)
                 .await?;
 
-            // Update the mapping
-            self.store_manager
-                .insert_value_index_mapping(&self.pool, &result.root_hash, result.element_index)
+        Ok(element_index.map(|index| index as usize))
+    }
+
+    /// Lowest block number accumulated so far, if any.
+    pub async fn get_first_accumulated_block(&self) -> Result<Option<u64>> {
+        let block_number: Option<i64> =
+            sqlx::query_scalar("SELECT MIN(block_number) FROM block_leaf_index")
+                .fetch_one(&self.pool)
+                .await?;
+
+        Ok(block_number.map(|number| number as u64))
+    }
+
+    /// Highest block number accumulated so far, if any.
+    pub async fn get_last_accumulated_block(&self) -> Result<Option<u64>> {
+        let block_number: Option<i64> =
+            sqlx::query_scalar("SELECT MAX(block_number) FROM block_leaf_index")
+                .fetch_one(&self.pool)
                 .await?;
+
+        Ok(block_number.map(|number| number as u64))
+    }
+
+    async fn get_stored_hash(&self, element_index: usize) -> Result<Felt> {
+        let hash = self
+            .mmr
+            .hashes
+            .get(SubKey::Usize(element_index))
+            .await?
+            .ok_or_else(|| eyre::eyre!("No hash stored at index {}", element_index))?;
+        parse_felt(&hash)
+    }
+
+    async fn get_peaks(&self) -> Result<Vec<Felt>> {
+        self.mmr
+            .get_peaks(PeaksOptions::default())
+            .await?
+            .iter()
+            .map(|peak| parse_felt(peak))
+            .collect()
+    }
+
+    /// Counts, peaks, root and block range of the MMR in the store.
+    pub async fn state(&self) -> Result<MmrState> {
+        let elements_count = self.mmr.elements_count.get().await?;
+        let peaks = self.get_peaks().await?;
+        let block_range = match (
+            self.get_first_accumulated_block().await?,
+            self.get_last_accumulated_block().await?,
+        ) {
+            (Some(first_block), Some(last_block)) => Some((first_block, last_block)),
+            _ => None,
+        };
+
+        Ok(MmrState {
+            elements_count,
+            leaves_count: self.mmr.leaves_count.get().await?,
+            root: calculate_root_hash(self.hasher, &peaks, elements_count),
+            peaks,
+            hasher: self.hasher,
+            block_range,
+        })
+    }
+
+    /// Proof of the last batch, which the next batch is chained to, if any.
+    pub fn previous_proof(&self) -> Option<&BatchProof> {
+        self.previous_proof.as_ref()
+    }
+
+    /// Receipt of `previous_proof`, `None` if that batch was only executed.
+    pub fn previous_receipt(&self) -> Option<&Receipt> {
+        self.previous_receipt.as_ref()
+    }
+
+    /// Check that every parent node in the store is the hash of its stored
+    /// children, and that every element up to the elements count is present.
+    pub async fn check_consistency(&self) -> Result<()> {
+        let elements_count = self.mmr.elements_count.get().await?;
+        for element_index in 1..=elements_count {
+            let hash = self.get_stored_hash(element_index).await?;
+            let Some((left, right)) = children_indices(element_index) else {
+                continue;
+            };
+            let left_hash = self.get_stored_hash(left).await?;
+            let right_hash = self.get_stored_hash(right).await?;
+            if hash_children(self.hasher, &left_hash, &right_hash) != hash {
+                return Err(eyre::eyre!(
+                    "Node {} is not the hash of its children {} and {}",
+                    element_index,
+                    left,
+                    right
+                ));
+            }
         }
+        info!("MMR store is consistent up to element {}", elements_count);
+        Ok(())
+    }
 
-        // Update peaks
-        let peaks_indices = find_peaks(guest_output.elements_count);
-        for (peak_hash, &peak_idx) in guest_output.final_peaks.iter().zip(peaks_indices.iter()) {
-            debug!("  Storing peak at index {}: {}", peak_idx, peak_hash);
-            self.mmr
-                .hashes
-                .set(peak_hash, SubKey::Usize(peak_idx))
+    /// Append `start_block..=end_block` in ascending order, so that leaf
+    /// positions follow block numbers.
+    pub async fn build_forward(
+        &mut self,
+        start_block: u64,
+        end_block: u64,
+    ) -> Result<Vec<BatchResult>> {
+        if start_block > end_block {
+            return Err(eyre::eyre!(
+                "Invalid block range: {} to {}",
+                start_block,
+                end_block
+            ));
+        }
+        self.check_block_mmr().await?;
+
+        self.calibrate_batch_size(
+            start_block,
+            start_block.saturating_add(SAMPLE_BLOCKS - 1).min(end_block),
+            Direction::Forward,
+        )
+        .await?;
+        if self.parallel_proving.is_some() {
+            let ranges = forward_ranges(start_block, end_block, self.batch_size);
+            return self
+                .process_batches_parallel(ranges, Direction::Forward)
+                .await;
+        }
+        self.current_batch = 0;
+
+        let mut batch_results = Vec::new();
+        let mut current_start = start_block;
+
+        while current_start <= end_block {
+            // The batch size may change between batches
+            self.total_batches =
+                self.current_batch + (end_block - current_start + 1).div_ceil(self.batch_size);
+            let batch_end = current_start
+                .saturating_add(self.batch_size - 1)
+                .min(end_block);
+            info!(
+                "Processing batch {}/{}: {} to {}",
+                self.current_batch + 1,
+                self.total_batches,
+                current_start,
+                batch_end
+            );
+
+            let result = self
+                .process_batch(current_start, batch_end, Direction::Forward)
                 .await?;
+            batch_results.push(result);
+            current_start = batch_end + 1;
         }
 
-        // Verify the state was properly updated
-        let stored_peaks = self.mmr.get_peaks(PeaksOptions::default()).await?;
-        debug!("Verified stored peaks: {:?}", stored_peaks);
+        Ok(batch_results)
+    }
 
-        if stored_peaks != guest_output.final_peaks {
-            return Err(eyre::eyre!("Failed to verify stored peaks after update"));
+    /// Append `end_block..=start_block` in descending order, as the backward
+    /// builds do.
+    async fn build_backward(
+        &mut self,
+        start_block: u64,
+        end_block: u64,
+    ) -> Result<Vec<BatchResult>> {
+        self.check_block_mmr().await?;
+        self.calibrate_batch_size(
+            end_block.saturating_sub(SAMPLE_BLOCKS - 1).max(start_block),
+            end_block,
+            Direction::Backward,
+        )
+        .await?;
+        if self.parallel_proving.is_some() {
+            let ranges = backward_ranges(end_block, self.batch_size, None)
+                .into_iter()
+                .take_while(|&(_, batch_end)| batch_end >= start_block)
+                .map(|(batch_start, batch_end)| (batch_start.max(start_block), batch_end))
+                .collect();
+            return self
+                .process_batches_parallel(ranges, Direction::Backward)
+                .await;
+        }
+        self.current_batch = 0;
+
+        let mut batch_results = Vec::new();
+        let mut current_end = end_block;
+
+        loop {
+            // The batch size may change between batches
+            self.total_batches =
+                self.current_batch + (current_end - start_block + 1).div_ceil(self.batch_size);
+            let batch_start = current_end
+                .saturating_sub(self.batch_size - 1)
+                .max(start_block);
+            info!(
+                "Processing batch {}/{}: {} to {}",
+                self.current_batch + 1,
+                self.total_batches,
+                batch_start,
+                current_end
+            );
+
+            let result = self
+                .process_batch(batch_start, current_end, Direction::Backward)
+                .await?;
+            batch_results.push(result);
+            if batch_start == start_block {
+                break;
+            }
+            current_end = batch_start - 1;
+        }
+
+        Ok(batch_results)
+    }
+
+    /// Append exactly `start_block..=end_block`. The range must continue the
+    /// MMR: right above its last block, or right below its first block for an
+    /// MMR built backwards. Any range starts an empty MMR, in ascending order.
+    pub async fn prove_range(
+        &mut self,
+        start_block: u64,
+        end_block: u64,
+    ) -> Result<Vec<BatchResult>> {
+        if start_block > end_block {
+            return Err(eyre::eyre!(
+                "Invalid block range: {} to {}",
+                start_block,
+                end_block
+            ));
+        }
+
+        match self.state().await?.block_range {
+            None => self.build_forward(start_block, end_block).await,
+            Some((_, last_block)) if start_block == last_block + 1 => {
+                self.build_forward(start_block, end_block).await
+            }
+            Some((first_block, _)) if end_block + 1 == first_block => {
+                self.build_backward(start_block, end_block).await
+            }
+            Some((first_block, last_block)) => Err(eyre::eyre!(
+                "Blocks {} to {} do not continue the MMR, which holds blocks {} to {}",
+                start_block,
+                end_block,
+                first_block,
+                last_block
+            )),
+        }
+    }
+
+    /// Append every finalized block newer than the last accumulated one.
+    pub async fn extend_to_finalized(&mut self) -> Result<Vec<BatchResult>> {
+        let finalized_block_number = self.header_source.finalized_block_number().await?;
+
+        let start_block = match self.get_last_accumulated_block().await? {
+            Some(last_block) => last_block + 1,
+            None => 0,
+        };
+
+        if start_block > finalized_block_number {
+            info!("MMR is up to date at block {}", finalized_block_number);
+            return Ok(Vec::new());
+        }
+
+        self.build_forward(start_block, finalized_block_number)
+            .await
+    }
+
+    /// Append `leaves`, arbitrary felts instead of block hashes, in batches
+    /// of the batch size. The results report the positions of the first and
+    /// last leaf of each batch as their block range. Batches are always
+    /// chained, even with parallel proving configured.
+    pub async fn append_leaves(&mut self, leaves: Vec<Felt>) -> Result<Vec<BatchResult>> {
+        if leaves.is_empty() {
+            return Ok(Vec::new());
+        }
+        // Leaves are indexed by block number, which generic leaves do not have
+        if let Some((first_block, last_block)) = self.state().await?.block_range {
+            return Err(eyre::eyre!(
+                "The MMR holds the hashes of blocks {} to {}, generic leaves cannot be appended",
+                first_block,
+                last_block
+            ));
         }
 
+        self.current_batch = 0;
+
+        let mut batch_results = Vec::new();
+        let mut batch_start = self.mmr.leaves_count.get().await? as u64;
+        let mut remaining = leaves.as_slice();
+
+        while !remaining.is_empty() {
+            // The batch size may change between batches
+            self.total_batches =
+                self.current_batch + (remaining.len() as u64).div_ceil(self.batch_size);
+            let (batch, rest) = remaining.split_at(remaining.len().min(self.batch_size as usize));
+            info!(
+                "Processing batch {}/{}: leaves {} to {}",
+                self.current_batch + 1,
+                self.total_batches,
+                batch_start,
+                batch_start + batch.len() as u64 - 1
+            );
+
+            let result = self
+                .process_leaves_batch(batch_start, batch.to_vec())
+                .await?;
+            batch_results.push(result);
+            batch_start += batch.len() as u64;
+            remaining = rest;
+        }
+
+        Ok(batch_results)
+    }
+
+    /// Refuse to append blocks to an MMR of generic leaves, i.e. one holding
+    /// leaves but no block index. Its batches could not chain into a block
+    /// batch, and block numbers would be counted from block #0.
+    async fn check_block_mmr(&self) -> Result<()> {
+        let leaves_count = self.mmr.leaves_count.get().await?;
+        if leaves_count > 0 && self.get_last_accumulated_block().await?.is_none() {
+            return Err(eyre::eyre!(
+                "The MMR holds {} generic leaves, blocks cannot be appended to it",
+                leaves_count
+            ));
+        }
         Ok(())
     }
 
     /// Build the MMR using a specified number of batches
     pub async fn build_with_num_batches(&mut self, num_batches: u64) -> Result<Vec<BatchResult>> {
-        let (finalized_block_number, _) = get_finalized_block_hash().await?;
+        self.check_block_mmr().await?;
+        let Some(mut current_end) = self.backward_start_block().await? else {
+            return Ok(Vec::new());
+        };
+        self.calibrate_batch_size(
+            current_end.saturating_sub(SAMPLE_BLOCKS - 1),
+            current_end,
+            Direction::Backward,
+        )
+        .await?;
+        if self.parallel_proving.is_some() {
+            let ranges = backward_ranges(current_end, self.batch_size, Some(num_batches));
+            return self
+                .process_batches_parallel(ranges, Direction::Backward)
+                .await;
+        }
         self.total_batches = num_batches;
         self.current_batch = 0;
-        self.previous_proofs.clear();
 
         let mut batch_results = Vec::new();
-        let mut current_end = finalized_block_number;
 
         for _ in 0..num_batches {
             if current_end == 0 {
@@ -206,7 +1559,9 @@ impl AccumulatorBuilder {
                 current_end
             );
 
-            let result = self.process_batch(start_block, current_end).await?;
+            let result = self
+                .process_batch(start_block, current_end, Direction::Backward)
+                .await?;
             batch_results.push(result);
             current_end = start_block.saturating_sub(1);
         }
@@ -215,16 +1570,30 @@ impl AccumulatorBuilder {
     }
 
     pub async fn build_from_finalized(&mut self) -> Result<Vec<BatchResult>> {
-        let (finalized_block_number, _) = get_finalized_block_hash().await?;
+        self.check_block_mmr().await?;
+        let Some(mut current_end) = self.backward_start_block().await? else {
+            return Ok(Vec::new());
+        };
 
-        self.total_batches = (finalized_block_number / self.batch_size as u64) + 1;
+        self.calibrate_batch_size(
+            current_end.saturating_sub(SAMPLE_BLOCKS - 1),
+            current_end,
+            Direction::Backward,
+        )
+        .await?;
+        if self.parallel_proving.is_some() {
+            let ranges = backward_ranges(current_end, self.batch_size, None);
+            return self
+                .process_batches_parallel(ranges, Direction::Backward)
+                .await;
+        }
         self.current_batch = 0;
-        self.previous_proofs.clear(); // Clear any existing proofs
 
         let mut batch_results = Vec::new();
-        let mut current_end = finalized_block_number;
 
         while current_end > 0 {
+            // The batch size may change between batches
+            self.total_batches = self.current_batch + (current_end / self.batch_size as u64) + 1;
             let start_block = current_end.saturating_sub(self.batch_size as u64 - 1);
             info!(
                 "Processing batch {}/{}: {} to {}",
@@ -234,11 +1603,111 @@ impl AccumulatorBuilder {
                 current_end
             );
 
-            let result = self.process_batch(start_block, current_end).await?;
+            let result = self
+                .process_batch(start_block, current_end, Direction::Backward)
+                .await?;
             batch_results.push(result);
             current_end = start_block.saturating_sub(1);
         }
 
         Ok(batch_results)
     }
+
+    /// First block of a backward build: the finalized block for a new MMR, or
+    /// the block below the lowest accumulated one when resuming. `None` once
+    /// block #0 has been accumulated.
+    async fn backward_start_block(&self) -> Result<Option<u64>> {
+        match self.get_first_accumulated_block().await? {
+            Some(first_block) => {
+                info!("Resuming below block {}", first_block);
+                Ok(first_block.checked_sub(1))
+            }
+            None => {
+                let finalized_block_number = self.header_source.finalized_block_number().await?;
+                Ok(Some(finalized_block_number))
+            }
+        }
+    }
+}
+
+/// Check that the batch emitted exactly the nodes between the current and
+/// the new elements count, in insertion order.
+fn check_new_nodes(guest_output: &GuestOutput, current_elements_count: usize) -> Result<()> {
+    let expected = current_elements_count + 1..=guest_output.elements_count;
+    if guest_output.new_nodes.len() != expected.clone().count()
+        || !guest_output
+            .new_nodes
+            .iter()
+            .zip(expected)
+            .all(|(node, index)| node.index == index)
+    {
+        return Err(eyre::eyre!(
+            "Invalid state transition: new nodes do not cover elements {} to {}",
+            current_elements_count + 1,
+            guest_output.elements_count
+        ));
+    }
+    Ok(())
+}
+
+/// MMR elements of the hashes of `headers`, in an MMR built with `hasher`.
+fn block_elements(hasher: HasherKind, headers: &[FetchedHeader]) -> Result<Vec<Felt>> {
+    headers
+        .iter()
+        .map(|fetched| &fetched.header)
+        .map(|h| {
+            felt::block_hash_to_felt(hasher, &h.block_hash)
+                .ok_or_else(|| eyre::eyre!("Invalid block hash: {}", h.block_hash))
+        })
+        .collect()
+}
+
+/// `start_block..=end_block` split in ascending batches of `batch_size` blocks.
+fn forward_ranges(start_block: u64, end_block: u64, batch_size: u64) -> Vec<(u64, u64)> {
+    let mut ranges = Vec::new();
+    let mut current_start = start_block;
+    while current_start <= end_block {
+        let batch_end = current_start.saturating_add(batch_size - 1).min(end_block);
+        ranges.push((current_start, batch_end));
+        current_start = batch_end + 1;
+    }
+    ranges
+}
+
+/// Descending batches of `batch_size` blocks from `end_block` down to block
+/// #0, at most `max_batches` of them, as the backward builds process them.
+fn backward_ranges(
+    mut end_block: u64,
+    batch_size: u64,
+    max_batches: Option<u64>,
+) -> Vec<(u64, u64)> {
+    let mut ranges = Vec::new();
+    while end_block > 0 && !max_batches.is_some_and(|max| ranges.len() as u64 >= max) {
+        let start_block = end_block.saturating_sub(batch_size - 1);
+        ranges.push((start_block, end_block));
+        end_block = start_block.saturating_sub(1);
+    }
+    ranges
+}
+
+fn parse_felt(value: &str) -> Result<Felt> {
+    felt::from_hex(value).ok_or_else(|| eyre::eyre!("Invalid felt: {}", value))
+}
+
+/// Write a key directly to the `store` table backing the MMR, so that it can
+/// take part in a transaction.
+async fn set_store_value(conn: &mut SqliteConnection, key: &str, value: &str) -> Result<()> {
+    sqlx::query("INSERT OR REPLACE INTO store (key, value) VALUES (?, ?)")
+        .bind(key)
+        .bind(value)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+async fn get_store_value(conn: &mut SqliteConnection, key: &str) -> Result<Option<String>> {
+    Ok(sqlx::query_scalar("SELECT value FROM store WHERE key = ?")
+        .bind(key)
+        .fetch_optional(conn)
+        .await?)
 }
@@ -0,0 +1,186 @@
+//! On-disk archive of every proof generated by the accumulator, so that proofs
+//! can be re-submitted, audited or reused without proving again.
+//!
+//! Layout of an artifact directory:
+//! - `manifest.json`: the `Manifest`, listing every artifact
+//! - `<image id>/<start block>-<end block>.proof`: bincode encoded `ProofType`
+//! - `<image id>/<start block>-<end block>.calldata.json`: Starknet calldata of
+//!   Groth16 proofs, as hex felts
+//! - `<image id>/<start block>-<end block>.side.json`: the `BatchSideOutput`
+//!   of a compact journal, needed to check it against its append digest
+use crate::types::{BatchResult, ProofType};
+use eyre::Result;
+use guest_types::{felt, BatchSideOutput};
+use risc0_zkvm::sha::{Digest, Impl, Sha256};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Version of the artifact layout and manifest format.
+pub const ARTIFACTS_VERSION: u32 = 1;
+
+const MANIFEST_FILE: &str = "manifest.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    pub artifacts: Vec<ArtifactEntry>,
+}
+
+/// A stored proof, keyed by its block range and image ID.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArtifactEntry {
+    pub start_block: u64,
+    pub end_block: u64,
+    /// Hex image ID of the guest that produced the proof
+    pub image_id: String,
+    /// "stark", "groth16" or "unproven"
+    pub kind: String,
+    /// Hex SHA-256 digest of the journal, as committed in the receipt claim
+    pub journal_digest: String,
+    /// Path of the proof, relative to the artifact directory
+    pub proof_file: String,
+    /// Path of the Starknet calldata of a Groth16 proof
+    pub calldata_file: Option<String>,
+    /// Path of the side output of a compact journal
+    #[serde(default)]
+    pub side_output_file: Option<String>,
+}
+
+pub struct ArtifactStore {
+    dir: PathBuf,
+    manifest: Manifest,
+}
+
+impl ArtifactStore {
+    /// Open the artifact directory at `dir`, creating it if needed.
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+
+        let manifest_path = dir.join(MANIFEST_FILE);
+        let manifest = if manifest_path.exists() {
+            let manifest: Manifest = serde_json::from_slice(&fs::read(&manifest_path)?)?;
+            if manifest.version != ARTIFACTS_VERSION {
+                return Err(eyre::eyre!(
+                    "Unsupported artifacts version {} in {}",
+                    manifest.version,
+                    manifest_path.display()
+                ));
+            }
+            manifest
+        } else {
+            Manifest {
+                version: ARTIFACTS_VERSION,
+                artifacts: Vec::new(),
+            }
+        };
+
+        Ok(Self { dir, manifest })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn manifest(&self) -> &Manifest {
+        &self.manifest
+    }
+
+    /// Store the proof of `result`, replacing any artifact with the same block
+    /// range and image ID. Results without a proof are skipped.
+    pub fn save(&mut self, result: &BatchResult) -> Result<()> {
+        let Some(proof) = &result.proof else {
+            return Ok(());
+        };
+
+        let image_id = hex::encode(Digest::from(result.image_id).as_bytes());
+        let name = format!("{}-{}", result.start_block, result.end_block);
+        fs::create_dir_all(self.dir.join(&image_id))?;
+
+        let proof_file = format!("{}/{}.proof", image_id, name);
+        write_atomically(&self.dir.join(&proof_file), &bincode::serialize(proof)?)?;
+
+        let calldata_file = match proof {
+            ProofType::Groth16 { calldata, .. } => {
+                let calldata: Vec<String> = calldata.iter().map(felt::to_hex).collect();
+                let calldata_file = format!("{}/{}.calldata.json", image_id, name);
+                write_atomically(
+                    &self.dir.join(&calldata_file),
+                    &serde_json::to_vec_pretty(&calldata)?,
+                )?;
+                Some(calldata_file)
+            }
+            _ => None,
+        };
+
+        let side_output_file = match &result.side_output {
+            Some(side_output) => {
+                let side_output_file = format!("{}/{}.side.json", image_id, name);
+                write_atomically(
+                    &self.dir.join(&side_output_file),
+                    &serde_json::to_vec_pretty(side_output)?,
+                )?;
+                Some(side_output_file)
+            }
+            None => None,
+        };
+
+        let entry = ArtifactEntry {
+            start_block: result.start_block,
+            end_block: result.end_block,
+            image_id,
+            kind: proof.kind().to_string(),
+            journal_digest: journal_digest(proof.journal()),
+            proof_file,
+            calldata_file,
+            side_output_file,
+        };
+        self.manifest.artifacts.retain(|artifact| {
+            (artifact.start_block, artifact.end_block, &artifact.image_id)
+                != (entry.start_block, entry.end_block, &entry.image_id)
+        });
+        self.manifest.artifacts.push(entry);
+        write_atomically(
+            &self.dir.join(MANIFEST_FILE),
+            &serde_json::to_vec_pretty(&self.manifest)?,
+        )
+    }
+
+    /// Read back the proof of a manifest entry, checking it against the
+    /// recorded journal digest.
+    pub fn load_proof(&self, entry: &ArtifactEntry) -> Result<ProofType> {
+        let proof: ProofType = bincode::deserialize(&fs::read(self.dir.join(&entry.proof_file))?)?;
+        if journal_digest(proof.journal()) != entry.journal_digest {
+            return Err(eyre::eyre!(
+                "Journal of {} does not match its digest in the manifest",
+                entry.proof_file
+            ));
+        }
+        Ok(proof)
+    }
+
+    /// Read back the side output of a manifest entry, if it has one. It is
+    /// checked when the compact journal is expanded with it.
+    pub fn load_side_output(&self, entry: &ArtifactEntry) -> Result<Option<BatchSideOutput>> {
+        entry
+            .side_output_file
+            .as_ref()
+            .map(|file| Ok(serde_json::from_slice(&fs::read(self.dir.join(file))?)?))
+            .transpose()
+    }
+}
+
+/// Hex SHA-256 digest of a journal.
+pub fn journal_digest(journal: &[u8]) -> String {
+    hex::encode(Impl::hash_bytes(journal).as_bytes())
+}
+
+/// Write through a temporary file, so that a crash never leaves a truncated
+/// artifact or manifest behind.
+fn write_atomically(path: &Path, contents: &[u8]) -> Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, contents)?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
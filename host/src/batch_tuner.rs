@@ -0,0 +1,85 @@
+//! Batch size tuning from the cycles measured per header.
+use crate::types::ProofStats;
+
+/// Cycles of a segment with the zkVM's default segment limit of 2^20.
+const SEGMENT_CYCLES: u64 = 1 << 20;
+
+/// Share of the cycle budget kept free for headers costlier than the ones
+/// measured so far.
+const HEADROOM: f64 = 0.1;
+
+/// Relative difference between the current and the ideal batch size below
+/// which the current size is kept.
+const DRIFT_TOLERANCE: f64 = 0.1;
+
+/// What a batch must fit in.
+#[derive(Debug, Clone, Copy)]
+pub enum TuningTarget {
+    /// At most this many segments per batch
+    Segments(u64),
+    /// At most `budget_bytes` of memory, with `segment_bytes` estimated to be
+    /// held per segment
+    Memory {
+        budget_bytes: u64,
+        segment_bytes: u64,
+    },
+}
+
+impl TuningTarget {
+    fn max_segments(self) -> u64 {
+        let segments = match self {
+            TuningTarget::Segments(segments) => segments,
+            TuningTarget::Memory {
+                budget_bytes,
+                segment_bytes,
+            } => budget_bytes / segment_bytes.max(1),
+        };
+        segments.max(1)
+    }
+}
+
+/// Picks the largest batch size fitting a `TuningTarget` from the user cycles
+/// per header of the batches run so far.
+#[derive(Debug, Clone)]
+pub struct BatchSizeTuner {
+    target: TuningTarget,
+    max_batch_size: u64,
+    /// Moving average over the observed batches
+    cycles_per_header: Option<f64>,
+}
+
+impl BatchSizeTuner {
+    pub fn new(target: TuningTarget, max_batch_size: u64) -> Self {
+        Self {
+            target,
+            max_batch_size: max_batch_size.max(1),
+            cycles_per_header: None,
+        }
+    }
+
+    /// Record the stats of a run over `headers` headers. Returns the new batch
+    /// size if the ideal size drifted away from `batch_size`.
+    pub fn observe(&mut self, headers: usize, stats: &ProofStats, batch_size: u64) -> Option<u64> {
+        if headers == 0 {
+            return None;
+        }
+
+        let measured = stats.user_cycles as f64 / headers as f64;
+        self.cycles_per_header = Some(match self.cycles_per_header {
+            Some(average) => (average + measured) / 2.0,
+            None => measured,
+        });
+
+        let ideal = self.batch_size()?;
+        let drift = (ideal as f64 - batch_size as f64).abs() / batch_size.max(1) as f64;
+        (drift > DRIFT_TOLERANCE).then_some(ideal)
+    }
+
+    /// Largest batch size fitting the target, once a run has been observed.
+    pub fn batch_size(&self) -> Option<u64> {
+        let cycles_per_header = self.cycles_per_header?;
+        let budget = (self.target.max_segments() * SEGMENT_CYCLES) as f64 * (1.0 - HEADROOM);
+        let batch_size = (budget / cycles_per_header.max(1.0)) as u64;
+        Some(batch_size.clamp(1, self.max_batch_size))
+    }
+}
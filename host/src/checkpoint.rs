@@ -0,0 +1,189 @@
+// host/src/checkpoint.rs
+use crate::types::{BatchCheckpoint, ProofStats, ProofType};
+use eyre::Result;
+use guest_types::{felt, JournalFormat, ProofKind};
+use sqlx::SqliteConnection;
+use store::SqlitePool;
+
+/// Record a completed batch. Checkpoints are written in the same transaction
+/// as the batch's resulting MMR state.
+pub async fn save_checkpoint(
+    conn: &mut SqliteConnection,
+    checkpoint: &BatchCheckpoint,
+) -> Result<()> {
+    let proof = bincode::serialize(&checkpoint.proof)?;
+    let peaks: Vec<String> = checkpoint.peaks.iter().map(felt::to_hex).collect();
+    let peaks = serde_json::to_string(&peaks)?;
+    let proven = !matches!(checkpoint.proof, ProofType::Unproven { .. });
+    let chain_receipt = checkpoint
+        .chain_receipt
+        .as_ref()
+        .map(bincode::serialize)
+        .transpose()?;
+    let stats = checkpoint.stats.as_ref();
+    let phase_cycles = stats
+        .and_then(|stats| stats.phase_cycles.as_ref())
+        .map(serde_json::to_string)
+        .transpose()?;
+
+    sqlx::query(
+        "INSERT INTO batch_checkpoints (start_block, end_block, journal, proof, peaks, elements_count, leaves_count, journal_format, hasher, proven, total_cycles, user_cycles, segments, elapsed_ms, phase_cycles, chain_receipt, chainable, proof_kind) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(checkpoint.start_block as i64)
+    .bind(checkpoint.end_block as i64)
+    .bind(&checkpoint.journal)
+    .bind(proof)
+    .bind(peaks)
+    .bind(checkpoint.elements_count as i64)
+    .bind(checkpoint.leaves_count as i64)
+    .bind(format_name(checkpoint.journal_format))
+    .bind(checkpoint.hasher.name())
+    .bind(proven)
+    .bind(stats.map(|stats| stats.total_cycles as i64))
+    .bind(stats.map(|stats| stats.user_cycles as i64))
+    .bind(stats.map(|stats| stats.segments as i64))
+    .bind(stats.map(|stats| stats.elapsed_ms as i64))
+    .bind(phase_cycles)
+    .bind(chain_receipt)
+    .bind(checkpoint.chainable)
+    .bind(kind_name(checkpoint.proof_kind))
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+#[derive(sqlx::FromRow)]
+struct CheckpointRow {
+    start_block: i64,
+    end_block: i64,
+    journal: Vec<u8>,
+    proof: Vec<u8>,
+    peaks: String,
+    elements_count: i64,
+    leaves_count: i64,
+    journal_format: String,
+    hasher: String,
+    total_cycles: Option<i64>,
+    user_cycles: Option<i64>,
+    segments: Option<i64>,
+    elapsed_ms: Option<i64>,
+    phase_cycles: Option<String>,
+    chain_receipt: Option<Vec<u8>>,
+    chainable: bool,
+    proof_kind: String,
+}
+
+const CHECKPOINT_COLUMNS: &str = "start_block, end_block, journal, proof, peaks, elements_count, leaves_count, journal_format, hasher, total_cycles, user_cycles, segments, elapsed_ms, phase_cycles, chain_receipt, chainable, proof_kind";
+
+/// Load the most recently completed batch, if any.
+pub async fn load_last_checkpoint(pool: &SqlitePool) -> Result<Option<BatchCheckpoint>> {
+    let row: Option<CheckpointRow> = sqlx::query_as(&format!(
+        "SELECT {} FROM batch_checkpoints ORDER BY id DESC LIMIT 1",
+        CHECKPOINT_COLUMNS
+    ))
+    .fetch_optional(pool)
+    .await?;
+    row.map(into_checkpoint).transpose()
+}
+
+/// Load the most recent checkpoint later batches can chain from, if any.
+pub async fn load_last_chainable_checkpoint(pool: &SqlitePool) -> Result<Option<BatchCheckpoint>> {
+    let row: Option<CheckpointRow> = sqlx::query_as(&format!(
+        "SELECT {} FROM batch_checkpoints WHERE chainable ORDER BY id DESC LIMIT 1",
+        CHECKPOINT_COLUMNS
+    ))
+    .fetch_optional(pool)
+    .await?;
+    row.map(into_checkpoint).transpose()
+}
+
+/// Delete the checkpoints recorded after the last chainable one, i.e. the
+/// segments of a parallel build whose aggregation was never checkpointed.
+pub async fn delete_unchainable_checkpoints(conn: &mut SqliteConnection) -> Result<()> {
+    sqlx::query(
+        "DELETE FROM batch_checkpoints WHERE id > COALESCE((SELECT MAX(id) FROM batch_checkpoints WHERE chainable), 0)",
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+fn into_checkpoint(row: CheckpointRow) -> Result<BatchCheckpoint> {
+    let proof: ProofType = bincode::deserialize(&row.proof)?;
+    let peaks = serde_json::from_str::<Vec<String>>(&row.peaks)?
+        .iter()
+        .map(|peak| felt::from_hex(peak).ok_or_else(|| eyre::eyre!("Invalid peak: {}", peak)))
+        .collect::<Result<_>>()?;
+    let stats = match (
+        row.total_cycles,
+        row.user_cycles,
+        row.segments,
+        row.elapsed_ms,
+    ) {
+        (Some(total_cycles), Some(user_cycles), Some(segments), Some(elapsed_ms)) => {
+            Some(ProofStats {
+                total_cycles: total_cycles as u64,
+                user_cycles: user_cycles as u64,
+                segments: segments as usize,
+                elapsed_ms: elapsed_ms as u64,
+                phase_cycles: row
+                    .phase_cycles
+                    .as_deref()
+                    .map(serde_json::from_str)
+                    .transpose()?,
+            })
+        }
+        _ => None,
+    };
+
+    Ok(BatchCheckpoint {
+        start_block: row.start_block as u64,
+        end_block: row.end_block as u64,
+        journal: row.journal,
+        proof,
+        peaks,
+        elements_count: row.elements_count as usize,
+        leaves_count: row.leaves_count as usize,
+        journal_format: parse_format(&row.journal_format)?,
+        hasher: row.hasher.parse().map_err(|e: String| eyre::eyre!(e))?,
+        stats,
+        chain_receipt: row
+            .chain_receipt
+            .as_deref()
+            .map(bincode::deserialize)
+            .transpose()?,
+        chainable: row.chainable,
+        proof_kind: parse_kind(&row.proof_kind)?,
+    })
+}
+
+fn format_name(format: JournalFormat) -> &'static str {
+    match format {
+        JournalFormat::Full => "full",
+        JournalFormat::Compact => "compact",
+    }
+}
+
+fn parse_format(name: &str) -> Result<JournalFormat> {
+    match name {
+        "full" => Ok(JournalFormat::Full),
+        "compact" => Ok(JournalFormat::Compact),
+        _ => Err(eyre::eyre!("Unknown journal format: {}", name)),
+    }
+}
+
+fn kind_name(kind: ProofKind) -> &'static str {
+    match kind {
+        ProofKind::Batch => "batch",
+        ProofKind::Aggregation => "aggregation",
+    }
+}
+
+fn parse_kind(name: &str) -> Result<ProofKind> {
+    match name {
+        "batch" => Ok(ProofKind::Batch),
+        "aggregation" => Ok(ProofKind::Aggregation),
+        _ => Err(eyre::eyre!("Unknown proof kind: {}", name)),
+    }
+}
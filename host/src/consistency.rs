@@ -0,0 +1,199 @@
+//! Differential check of the guest MMR against the host `mmr` crate.
+//!
+//! The host store is maintained with the `mmr` crate while batches are
+//! appended by `GuestMMR` inside the zkVM. Both are replayed here over the
+//! same leaves, comparing every append result, peak set and bag.
+use crate::header_source::HeaderSource;
+use eyre::Result;
+use guest_mmr::GuestMMR;
+use guest_types::{felt, HasherKind};
+use mmr::PeaksOptions;
+use mmr_accumulator::{processor_utils::*, MMR};
+use starknet_crypto::{poseidon_hash, Felt};
+use std::fmt;
+
+/// A value compared after each append.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Check {
+    ElementIndex,
+    ElementsCount,
+    LeavesCount,
+    RootHash,
+    Peaks,
+    Bag,
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Check::ElementIndex => "element index",
+            Check::ElementsCount => "elements count",
+            Check::LeavesCount => "leaves count",
+            Check::RootHash => "root hash",
+            Check::Peaks => "peaks",
+            Check::Bag => "bag of the peaks",
+        })
+    }
+}
+
+/// The first value on which the two implementations disagree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// Position of the leaf in the replayed sequence
+    pub leaf: usize,
+    /// Element index the host assigned to the leaf
+    pub element_index: usize,
+    pub check: Check,
+    pub host: String,
+    pub guest: String,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} differs after appending leaf {} (element {}): host {}, guest {}",
+            self.check, self.leaf, self.element_index, self.host, self.guest
+        )
+    }
+}
+
+/// Deterministic pseudo random leaves, `poseidon(seed, i)` for the i-th leaf.
+pub fn synthetic_leaves(seed: u64, count: usize) -> Vec<Felt> {
+    (0..count)
+        .map(|i| poseidon_hash(Felt::from(seed), Felt::from(i as u64)))
+        .collect()
+}
+
+/// The leaves of the blocks `start_block..=end_block` of `header_source`, in
+/// ascending order, encoded for a Poseidon MMR as `verify_consistency`
+/// compares.
+pub async fn block_leaves(
+    header_source: &dyn HeaderSource,
+    start_block: u64,
+    end_block: u64,
+) -> Result<Vec<Felt>> {
+    let headers = header_source
+        .headers_in_range(start_block, end_block)
+        .await?;
+    headers
+        .iter()
+        .map(|fetched| &fetched.header)
+        .map(|header| {
+            felt::block_hash_to_felt(HasherKind::Poseidon, &header.block_hash)
+                .ok_or_else(|| eyre::eyre!("Invalid block hash: {}", header.block_hash))
+        })
+        .collect()
+}
+
+/// Replay `leaves` through a fresh host MMR in a temporary store and
+/// through `GuestMMR`. Only Poseidon is compared, the hasher of the `mmr`
+/// crate.
+pub async fn verify_consistency(leaves: &[Felt], batch_size: usize) -> Result<Option<Divergence>> {
+    let store_dir = tempfile::tempdir()?;
+    let store_path = create_database_file(&store_dir.path().to_path_buf(), 0)?;
+    let (_store_manager, mut mmr, _pool) = initialize_mmr(&store_path).await?;
+
+    replay_leaves(&mut mmr, leaves, batch_size).await
+}
+
+/// Append `leaves` to `mmr` and to a `GuestMMR`, starting the latter over
+/// from the host's state every `batch_size` leaves as the accumulator does,
+/// and return the first divergence.
+pub async fn replay_leaves(
+    mmr: &mut MMR,
+    leaves: &[Felt],
+    batch_size: usize,
+) -> Result<Option<Divergence>> {
+    let batch_size = batch_size.max(1);
+
+    for (batch_index, batch) in leaves.chunks(batch_size).enumerate() {
+        let mut guest_mmr = GuestMMR::new(
+            host_peaks(mmr).await?,
+            mmr.elements_count.get().await?,
+            mmr.leaves_count.get().await?,
+        )
+        .map_err(|e| eyre::eyre!("Guest MMR rejected batch {}: {}", batch_index, e))?;
+
+        for (offset, leaf) in batch.iter().enumerate() {
+            let position = batch_index * batch_size + offset;
+            let host_result = mmr.append(felt::to_hex(leaf)).await?;
+            let guest_result = guest_mmr
+                .append(*leaf)
+                .map_err(|e| eyre::eyre!("Guest append failed at leaf {}: {}", position, e))?;
+
+            let guest_peaks = guest_mmr
+                .get_peaks(Default::default())
+                .map_err(|e| eyre::eyre!("Guest get peaks failed at leaf {}: {}", position, e))?;
+            let guest_bag = guest_mmr
+                .bag_the_peaks()
+                .map_err(|e| eyre::eyre!("Guest bagging failed at leaf {}: {}", position, e))?;
+            let host_peaks = mmr.get_peaks(PeaksOptions::default()).await?;
+            let host_bag = mmr.bag_the_peaks(None).await?;
+
+            let checks = [
+                (
+                    Check::ElementIndex,
+                    host_result.element_index.to_string(),
+                    guest_result.element_index.to_string(),
+                ),
+                (
+                    Check::ElementsCount,
+                    host_result.elements_count.to_string(),
+                    guest_result.elements_count.to_string(),
+                ),
+                (
+                    Check::LeavesCount,
+                    host_result.leaves_count.to_string(),
+                    guest_result.leaves_count.to_string(),
+                ),
+                (
+                    Check::RootHash,
+                    canonical_hex(&host_result.root_hash),
+                    felt::to_hex(&guest_result.root_hash),
+                ),
+                (
+                    Check::Peaks,
+                    format_list(host_peaks.iter().map(|peak| canonical_hex(peak))),
+                    format_list(guest_peaks.iter().map(felt::to_hex)),
+                ),
+                (
+                    Check::Bag,
+                    canonical_hex(&host_bag),
+                    felt::to_hex(&guest_bag),
+                ),
+            ];
+
+            if let Some((check, host, guest)) = checks.into_iter().find(|(_, h, g)| h != g) {
+                return Ok(Some(Divergence {
+                    leaf: position,
+                    element_index: host_result.element_index,
+                    check,
+                    host,
+                    guest,
+                }));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+async fn host_peaks(mmr: &MMR) -> Result<Vec<Felt>> {
+    mmr.get_peaks(PeaksOptions::default())
+        .await?
+        .iter()
+        .map(|peak| felt::from_hex(peak).ok_or_else(|| eyre::eyre!("Invalid peak: {}", peak)))
+        .collect()
+}
+
+/// Host hashes in the guest's hex format, or as is if they are not felts.
+fn canonical_hex(value: &str) -> String {
+    felt::from_hex(value)
+        .map(|value| felt::to_hex(&value))
+        .unwrap_or_else(|| value.to_string())
+}
+
+fn format_list(values: impl Iterator<Item = String>) -> String {
+    format!("[{}]", values.collect::<Vec<_>>().join(", "))
+}
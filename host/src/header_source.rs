@@ -0,0 +1,310 @@
+//! Where the accumulator reads block headers from.
+//!
+//! - `RpcHeaderSource`: the indexer database of `db_access` and the finalized
+//!   block of the Ethereum RPC, as used so far
+//! - `FileHeaderSource`: a JSON array or a JSONL file of `BlockHeader`s, to
+//!   run offline from recorded fixtures
+//! - `SqlHeaderSource`: a `block_headers` table of a SQLite or Postgres
+//!   database, holding each header as JSON
+//!
+//! `BlockHeader` has no field for the Prague `requests_hash`, which every
+//! source returns alongside the header. The JSON sources read it from a
+//! `requests_hash` or `requestsHash` key of the header.
+use alloy::eips::BlockNumberOrTag;
+use alloy::providers::{Provider, ProviderBuilder};
+use alloy::transports::Transport;
+use async_trait::async_trait;
+use block_validity::BlockHeader;
+use db_access::rpc::get_block_headers_in_range;
+use eyre::Result;
+use mmr_accumulator::ethereum::get_finalized_block_hash;
+use serde_json::Value;
+use sqlx::any::{install_default_drivers, AnyPoolOptions};
+use sqlx::AnyPool;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+/// Blocks requested from the RPC at once when reading requests hashes.
+const RPC_CONCURRENCY: usize = 16;
+
+/// A block header and its Prague `requests_hash`, `None` before Prague.
+#[derive(Debug, Clone)]
+pub struct FetchedHeader {
+    pub header: BlockHeader,
+    pub requests_hash: Option<String>,
+}
+
+impl FetchedHeader {
+    /// Parse a JSON `BlockHeader`, taking the requests hash out of it.
+    fn from_json(mut value: Value) -> Result<Self> {
+        let requests_hash = value.as_object_mut().and_then(|header| {
+            header
+                .remove("requests_hash")
+                .or_else(|| header.remove("requestsHash"))
+        });
+        let requests_hash = match requests_hash {
+            None | Some(Value::Null) => None,
+            Some(Value::String(hash)) => Some(hash),
+            Some(other) => return Err(eyre::eyre!("Invalid requests hash: {}", other)),
+        };
+
+        Ok(Self {
+            header: serde_json::from_value(value)?,
+            requests_hash,
+        })
+    }
+}
+
+#[async_trait]
+pub trait HeaderSource: Send + Sync {
+    /// Headers of the blocks `start_block..=end_block`, each exactly once and
+    /// in ascending order. Missing blocks are an error.
+    async fn headers_in_range(
+        &self,
+        start_block: u64,
+        end_block: u64,
+    ) -> Result<Vec<FetchedHeader>>;
+
+    /// Number of the latest finalized block.
+    async fn finalized_block_number(&self) -> Result<u64>;
+}
+
+/// Open the header source described by `spec`: a `sqlite:` or `postgres:`
+/// database URL, or the path of a JSON or JSONL file.
+pub async fn open_header_source(spec: &str) -> Result<Arc<dyn HeaderSource>> {
+    if spec.starts_with("sqlite:") || spec.starts_with("postgres:") {
+        Ok(Arc::new(SqlHeaderSource::connect(spec).await?))
+    } else {
+        Ok(Arc::new(FileHeaderSource::open(spec)?))
+    }
+}
+
+/// Headers from the `db_access` indexer database, finalized block from the
+/// Ethereum RPC. The indexer does not store requests hashes, they are read
+/// from the RPC at `ETH_RPC_URL`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RpcHeaderSource;
+
+impl RpcHeaderSource {
+    /// Requests hashes of the blocks `start_block..=end_block`. Forks only
+    /// activate upwards, so a range ending before Prague costs one request;
+    /// the blocks of a range after it are requested concurrently.
+    async fn requests_hashes(
+        &self,
+        start_block: u64,
+        end_block: u64,
+    ) -> Result<BTreeMap<u64, String>> {
+        let rpc_url = std::env::var("ETH_RPC_URL")
+            .map_err(|_| eyre::eyre!("ETH_RPC_URL should be provided as env var"))?;
+        let provider = ProviderBuilder::new().on_http(rpc_url.parse()?);
+
+        let Some(last_hash) = requests_hash(&provider, end_block).await? else {
+            return Ok(BTreeMap::new());
+        };
+
+        let requests = Arc::new(Semaphore::new(RPC_CONCURRENCY));
+        let mut tasks = JoinSet::new();
+        for number in start_block..end_block {
+            let provider = provider.clone();
+            let requests = requests.clone();
+            tasks.spawn(async move {
+                let _request = requests.acquire_owned().await?;
+                Ok::<_, eyre::Report>((number, requests_hash(&provider, number).await?))
+            });
+        }
+
+        let mut requests_hashes = BTreeMap::from([(end_block, last_hash)]);
+        while let Some(task) = tasks.join_next().await {
+            if let (number, Some(hash)) = task?? {
+                requests_hashes.insert(number, hash);
+            }
+        }
+        Ok(requests_hashes)
+    }
+}
+
+/// Requests hash of block `number`, `None` before Prague.
+async fn requests_hash<T: Transport + Clone, P: Provider<T>>(
+    provider: &P,
+    number: u64,
+) -> Result<Option<String>> {
+    let block = provider
+        .get_block_by_number(BlockNumberOrTag::Number(number), false)
+        .await?
+        .ok_or_else(|| eyre::eyre!("Block {} not found", number))?;
+    Ok(block.header.requests_hash.map(|hash| hash.to_string()))
+}
+
+#[async_trait]
+impl HeaderSource for RpcHeaderSource {
+    async fn headers_in_range(
+        &self,
+        start_block: u64,
+        end_block: u64,
+    ) -> Result<Vec<FetchedHeader>> {
+        let headers = get_block_headers_in_range(start_block, end_block).await?;
+        let mut requests_hashes = self.requests_hashes(start_block, end_block).await?;
+        let mut headers: Vec<FetchedHeader> = headers
+            .into_iter()
+            .map(|header| FetchedHeader {
+                requests_hash: requests_hashes.remove(&(header.number as u64)),
+                header,
+            })
+            .collect();
+        headers.sort_by_key(|fetched| fetched.header.number);
+        check_complete(&headers, start_block, end_block)?;
+        Ok(headers)
+    }
+
+    async fn finalized_block_number(&self) -> Result<u64> {
+        let (finalized_block_number, _) = get_finalized_block_hash().await?;
+        Ok(finalized_block_number)
+    }
+}
+
+/// Headers loaded from a file. The highest block of the file is taken as
+/// the finalized one.
+#[derive(Debug, Clone)]
+pub struct FileHeaderSource {
+    headers: BTreeMap<u64, FetchedHeader>,
+}
+
+impl FileHeaderSource {
+    /// Load `path`: one header per line if its extension is `jsonl`, else a
+    /// JSON array of headers.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)?;
+        let values: Vec<Value> = if path.extension().is_some_and(|ext| ext == "jsonl") {
+            contents
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(serde_json::from_str)
+                .collect::<Result<Vec<_>, _>>()?
+        } else {
+            serde_json::from_str(&contents)?
+        };
+        let headers = values
+            .into_iter()
+            .map(FetchedHeader::from_json)
+            .collect::<Result<Vec<_>>>()?;
+
+        Self::from_headers(headers)
+    }
+
+    /// Index `headers` by block number. A block found twice is an error,
+    /// rather than one of its headers being silently dropped.
+    pub fn from_headers(headers: impl IntoIterator<Item = FetchedHeader>) -> Result<Self> {
+        let mut by_number = BTreeMap::new();
+        for fetched in headers {
+            let number = fetched.header.number as u64;
+            if by_number.insert(number, fetched).is_some() {
+                return Err(eyre::eyre!("Found two headers of block {}", number));
+            }
+        }
+        Ok(Self { headers: by_number })
+    }
+}
+
+#[async_trait]
+impl HeaderSource for FileHeaderSource {
+    async fn headers_in_range(
+        &self,
+        start_block: u64,
+        end_block: u64,
+    ) -> Result<Vec<FetchedHeader>> {
+        let headers: Vec<FetchedHeader> = self
+            .headers
+            .range(start_block..=end_block)
+            .map(|(_, header)| header.clone())
+            .collect();
+        check_complete(&headers, start_block, end_block)?;
+        Ok(headers)
+    }
+
+    async fn finalized_block_number(&self) -> Result<u64> {
+        self.headers
+            .last_key_value()
+            .map(|(&number, _)| number)
+            .ok_or_else(|| eyre::eyre!("The header file is empty"))
+    }
+}
+
+/// Headers stored in a `block_headers (number BIGINT PRIMARY KEY, header TEXT)`
+/// table, `header` being the JSON encoded `BlockHeader`. The highest block of
+/// the table is taken as the finalized one.
+#[derive(Debug, Clone)]
+pub struct SqlHeaderSource {
+    pool: AnyPool,
+}
+
+impl SqlHeaderSource {
+    pub async fn connect(url: &str) -> Result<Self> {
+        install_default_drivers();
+        let pool = AnyPoolOptions::new().connect(url).await?;
+        Ok(Self { pool })
+    }
+}
+
+#[async_trait]
+impl HeaderSource for SqlHeaderSource {
+    async fn headers_in_range(
+        &self,
+        start_block: u64,
+        end_block: u64,
+    ) -> Result<Vec<FetchedHeader>> {
+        let rows: Vec<String> = sqlx::query_scalar(
+            "SELECT header FROM block_headers WHERE number BETWEEN $1 AND $2 ORDER BY number",
+        )
+        .bind(start_block as i64)
+        .bind(end_block as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let headers = rows
+            .iter()
+            .map(|row| FetchedHeader::from_json(serde_json::from_str(row)?))
+            .collect::<Result<Vec<_>>>()?;
+        check_complete(&headers, start_block, end_block)?;
+        Ok(headers)
+    }
+
+    async fn finalized_block_number(&self) -> Result<u64> {
+        let number: Option<i64> = sqlx::query_scalar("SELECT MAX(number) FROM block_headers")
+            .fetch_one(&self.pool)
+            .await?;
+        number
+            .map(|number| number as u64)
+            .ok_or_else(|| eyre::eyre!("The block_headers table is empty"))
+    }
+}
+
+/// Sources may have gaps or duplicates, which would otherwise surface as an
+/// MMR missing blocks. `headers` must hold every block of
+/// `start_block..=end_block` exactly once, in ascending order.
+pub fn check_complete(headers: &[FetchedHeader], start_block: u64, end_block: u64) -> Result<()> {
+    let expected = end_block - start_block + 1;
+    if headers.len() as u64 != expected {
+        return Err(eyre::eyre!(
+            "Found {} of the {} headers of blocks {} to {}",
+            headers.len(),
+            expected,
+            start_block,
+            end_block
+        ));
+    }
+    for (fetched, number) in headers.iter().zip(start_block..=end_block) {
+        if fetched.header.number as u64 != number {
+            return Err(eyre::eyre!(
+                "Expected the header of block {}, found block {}",
+                number,
+                fetched.header.number
+            ));
+        }
+    }
+    Ok(())
+}
@@ -1,3 +1,10 @@
 pub mod accumulator;
+pub mod artifacts;
+pub mod batch_tuner;
+pub mod checkpoint;
+pub mod consistency;
+pub mod header_source;
 pub mod proof_generator;
+pub mod report;
 pub mod types;
+pub mod verify;
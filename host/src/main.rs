@@ -1,30 +1,278 @@
-mod accumulator;
-mod proof_generator;
-mod types;
-use accumulator::AccumulatorBuilder;
-use clap::Parser;
+use clap::{Args, Parser, Subcommand};
 use eyre::Result;
-use methods::{MMR_GUEST_ELF, MMR_GUEST_ID};
+use guest_types::{felt, HasherKind, JournalFormat};
+use host::accumulator::AccumulatorBuilder;
+use host::artifacts::ArtifactStore;
+use host::batch_tuner::{BatchSizeTuner, TuningTarget};
+use host::consistency::{block_leaves, synthetic_leaves, verify_consistency};
+use host::header_source::{open_header_source, HeaderSource, RpcHeaderSource};
+use host::proof_generator::{ProofGenerator, ProofType};
+use host::report::write_report;
+use host::types::BatchResult;
+use host::verify::verify_artifact;
+use methods::{MMR_AGGREGATE_ELF, MMR_AGGREGATE_ID, MMR_GUEST_ELF, MMR_GUEST_ID};
 use mmr_accumulator::processor_utils::{create_database_file, ensure_directory_exists};
-use proof_generator::{ProofGenerator, ProofType};
+use starknet_crypto::Felt;
 use starknet_handler::verify_groth16_proof_onchain;
-use tracing::info;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::{info, warn};
 
-/// Struct to hold CLI arguments using `clap` derive macros
+/// CLI of the host, one subcommand per operation
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
-struct Args {
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Build the MMR backwards from the finalized block, or from below the
+    /// lowest accumulated block, down to block #0
+    Build {
+        /// Number of batches to process. If not specified, processes until block #0.
+        #[arg(short, long)]
+        num_batches: Option<u64>,
+
+        #[command(flatten)]
+        proving: ProvingArgs,
+    },
+    /// Append, in ascending order, the finalized blocks newer than the last accumulated one
+    Extend {
+        #[command(flatten)]
+        proving: ProvingArgs,
+    },
+    /// Append the blocks `--from` to `--to`, right above or right below the
+    /// blocks already accumulated
+    ProveRange {
+        /// First block of the range
+        #[arg(long)]
+        from: u64,
+
+        /// Last block of the range
+        #[arg(long)]
+        to: u64,
+
+        #[command(flatten)]
+        proving: ProvingArgs,
+    },
+    /// Append arbitrary felts, instead of block hashes, to an MMR of generic leaves
+    AppendLeaves {
+        /// JSON file holding an array of hex encoded felts, in append order
+        leaves: PathBuf,
+
+        #[command(flatten)]
+        proving: ProvingArgs,
+    },
+    /// Verify an archived proof against the guest image IDs and print its
+    /// decoded journal. Groth16 calldata is rebuilt and compared with the
+    /// stored one.
+    Verify {
+        /// `.proof` file in the artifacts directory
+        artifact: PathBuf,
+    },
+    /// Print the counts, peaks, root and block range of an MMR store
+    Inspect {
+        /// Path to the SQLite database file
+        #[arg(short, long)]
+        db_file: String,
+
+        /// Also check that every stored parent node is the hash of its
+        /// children, failing on the first one that is not
+        #[arg(long)]
+        check: bool,
+    },
+    /// Replay leaves through both the guest MMR and the host mmr crate and
+    /// report the first value they disagree on.
+    VerifyConsistency {
+        /// Number of leaves per batch
+        #[arg(short, long, default_value_t = 1024, value_parser = clap::value_parser!(u64).range(1..))]
+        batch_size: u64,
+
+        /// Number of pseudo random leaves to replay
+        #[arg(long, default_value_t = 4096)]
+        leaves: usize,
+
+        /// Seed of the pseudo random leaves
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+
+        /// Replay the hashes of the blocks from this one instead of random leaves
+        #[arg(long, requires = "to_block")]
+        from_block: Option<u64>,
+
+        /// Last block replayed with `--from-block`
+        #[arg(long, requires = "from_block")]
+        to_block: Option<u64>,
+
+        /// Read the headers from this source instead of the RPC, see `--headers` of `build`
+        #[arg(long, requires = "from_block")]
+        headers: Option<String>,
+    },
+}
+
+/// Options shared by the subcommands that append to the MMR
+#[derive(Args, Debug)]
+struct ProvingArgs {
     /// Batch size for processing blocks
-    #[arg(short, long, default_value_t = 1024)]
+    #[arg(short, long, default_value_t = 1024, value_parser = clap::value_parser!(u64).range(1..))]
     batch_size: u64,
 
     /// Path to the SQLite database file. If not specified, a new one will be created.
     #[arg(short, long)]
     db_file: Option<String>,
 
-    /// Number of batches to process. If not specified, processes until block #0.
-    #[arg(short, long)]
-    num_batches: Option<u64>,
+    /// Read the headers from a JSON or JSONL file of `BlockHeader`s, or from
+    /// a `sqlite:` or `postgres:` database URL, instead of the RPC. The
+    /// highest block found is taken as the finalized one.
+    #[arg(long)]
+    headers: Option<String>,
+
+    /// Commit only the MMR state and a digest of the appended leaves to each batch journal
+    #[arg(long)]
+    compact_journal: bool,
+
+    /// Hasher of a new MMR: poseidon (the default), keccak or sha256. An
+    /// existing MMR keeps its hasher, and is rejected if it differs from this.
+    #[arg(long)]
+    hasher: Option<HasherKind>,
+
+    /// Only execute the guest, without proving. Batches are still written to
+    /// the store, checkpointed as unproven.
+    #[arg(long)]
+    execute_only: bool,
+
+    /// Write the cycle counts and proving time of each batch to this JSON file
+    #[arg(long)]
+    report: Option<PathBuf>,
+
+    /// Tune the batch size so that each batch fits in this many segments.
+    /// `--batch-size` is only used until the first sample is measured.
+    #[arg(long, conflicts_with = "tune_memory_mb")]
+    tune_segments: Option<u64>,
+
+    /// Tune the batch size so that each batch fits in this much memory, in MB
+    #[arg(long)]
+    tune_memory_mb: Option<u64>,
+
+    /// Estimated memory held per segment, in MB, used by `--tune-memory-mb`
+    #[arg(long, default_value_t = 256)]
+    segment_memory_mb: u64,
+
+    /// Largest batch size picked by the tuning
+    #[arg(long, default_value_t = 8192, value_parser = clap::value_parser!(u64).range(1..))]
+    max_batch_size: u64,
+
+    /// Directory where the proof of every batch is archived, see `host::artifacts`
+    #[arg(long, default_value = "artifacts")]
+    artifacts_dir: PathBuf,
+
+    /// Prove the batches independently on this many workers, then aggregate
+    /// them into a single Groth16 proof, which later batches chain from.
+    #[arg(long)]
+    parallel_workers: Option<usize>,
+}
+
+impl ProvingArgs {
+    /// Accumulator over the store of `--db-file`, configured from the options.
+    async fn accumulator(&self) -> Result<AccumulatorBuilder> {
+        // Set up the database file path
+        let store_path = if let Some(db_file) = &self.db_file {
+            // If a database file is specified, use it
+            db_file.clone()
+        } else {
+            // Otherwise, create a new database file
+            let current_dir = ensure_directory_exists("db-instances")?;
+            create_database_file(&current_dir, 0)?
+        };
+
+        // Initialize proof generator
+        let proof_generator =
+            ProofGenerator::new(MMR_GUEST_ELF, MMR_GUEST_ID).with_execute_only(self.execute_only);
+
+        // Initialize accumulator builder with the batch size
+        let journal_format = if self.compact_journal {
+            JournalFormat::Compact
+        } else {
+            JournalFormat::Full
+        };
+        let mut builder = AccumulatorBuilder::new(&store_path, proof_generator, self.batch_size)
+            .await?
+            .with_journal_format(journal_format)
+            .with_artifacts(ArtifactStore::open(&self.artifacts_dir)?);
+        if let Some(hasher) = self.hasher {
+            builder = builder.with_hasher(hasher).await?;
+        }
+
+        let tuning_target = match (self.tune_segments, self.tune_memory_mb) {
+            (Some(segments), _) => Some(TuningTarget::Segments(segments)),
+            (None, Some(memory_mb)) => Some(TuningTarget::Memory {
+                budget_bytes: memory_mb << 20,
+                segment_bytes: self.segment_memory_mb << 20,
+            }),
+            (None, None) => None,
+        };
+        if let Some(target) = tuning_target {
+            builder =
+                builder.with_batch_size_tuning(BatchSizeTuner::new(target, self.max_batch_size));
+        }
+        if let Some(headers) = &self.headers {
+            builder = builder.with_header_source(open_header_source(headers).await?);
+        }
+        if let Some(workers) = self.parallel_workers {
+            let aggregation_prover = ProofGenerator::new(MMR_AGGREGATE_ELF, MMR_AGGREGATE_ID)
+                .with_execute_only(self.execute_only);
+            builder = builder.with_parallel_proving(workers, aggregation_prover);
+        }
+
+        Ok(builder)
+    }
+
+    /// Print the results of a run and write its report.
+    async fn finish(&self, results: &[BatchResult]) -> Result<()> {
+        for result in results {
+            info!(
+                "Processed blocks {} to {}",
+                result.start_block, result.end_block
+            );
+            match &result.proof {
+                Some(ProofType::Stark { .. }) => info!("Generated STARK proof"),
+                Some(ProofType::Groth16 { calldata, .. }) => {
+                    info!("Generated Groth16 proof");
+                    let result = verify_groth16_proof_onchain(calldata);
+                    info!(
+                        "Proof verification result: {:?}",
+                        result.await.expect("Failed to verify final Groth16 proof")
+                    );
+                }
+                Some(ProofType::Unproven { .. }) => info!("Executed without proving"),
+                None => info!("No proof generated"),
+            }
+            if let Some(stats) = &result.stats {
+                info!(
+                    "{} total cycles, {} user cycles, {} segments, {} ms",
+                    stats.total_cycles, stats.user_cycles, stats.segments, stats.elapsed_ms
+                );
+                if let Some(phases) = &stats.phase_cycles {
+                    info!(
+                        "Guest cycles: {} previous proof verification, {} header validation, {} MMR appends, {} peak extraction",
+                        phases.previous_proof_verification,
+                        phases.header_validation,
+                        phases.mmr_appends,
+                        phases.peak_extraction
+                    );
+                }
+            }
+        }
+
+        if let Some(report) = &self.report {
+            write_report(report, results)?;
+            info!("Wrote batch report to {}", report.display());
+        }
+
+        Ok(())
+    }
 }
 
 #[tokio::main]
@@ -34,51 +282,94 @@ async fn main() -> Result<()> {
         .init();
 
     // Parse CLI arguments
-    let args = Args::parse();
-
-    // Set up the database file path
-    let store_path = if let Some(db_file) = &args.db_file {
-        // If a database file is specified, use it
-        db_file.clone()
-    } else {
-        // Otherwise, create a new database file
-        let current_dir = ensure_directory_exists("db-instances")?;
-        create_database_file(&current_dir, 0)?
-    };
-
-    // Initialize proof generator
-    let proof_generator = ProofGenerator::new(MMR_GUEST_ELF, MMR_GUEST_ID);
-
-    // Initialize accumulator builder with the batch size
-    let mut builder =
-        AccumulatorBuilder::new(&store_path, proof_generator, args.batch_size).await?;
-
-    // Build MMR from finalized block to block #0 or up to the specified number of batches
-    let results = if let Some(num_batches) = args.num_batches {
-        builder.build_with_num_batches(num_batches).await?
-    } else {
-        builder.build_from_finalized().await?
-    };
-
-    // Print results
-    for result in &results {
-        info!(
-            "Processed blocks {} to {}",
-            result.start_block, result.end_block
-        );
-        match &result.proof {
-            Some(ProofType::Stark { .. }) => info!("Generated STARK proof"),
-            Some(ProofType::Groth16 { calldata, .. }) => {
-                info!("Generated Groth16 proof");
-                let result = verify_groth16_proof_onchain(&calldata);
-                info!(
-                    "Proof verification result: {:?}",
-                    result.await.expect("Failed to verify final Groth16 proof")
-                );
+    let cli = Cli::parse();
+
+    match cli.command {
+        Command::Build {
+            num_batches,
+            proving,
+        } => {
+            // Build MMR from finalized block to block #0 or up to the specified number of batches
+            let mut builder = proving.accumulator().await?;
+            let results = match num_batches {
+                Some(num_batches) => builder.build_with_num_batches(num_batches).await?,
+                None => builder.build_from_finalized().await?,
+            };
+            proving.finish(&results).await
+        }
+        Command::Extend { proving } => {
+            let results = proving.accumulator().await?.extend_to_finalized().await?;
+            proving.finish(&results).await
+        }
+        Command::ProveRange { from, to, proving } => {
+            let results = proving.accumulator().await?.prove_range(from, to).await?;
+            proving.finish(&results).await
+        }
+        Command::AppendLeaves { leaves, proving } => {
+            let leaves = read_leaves(&leaves)?;
+            let results = proving.accumulator().await?.append_leaves(leaves).await?;
+            proving.finish(&results).await
+        }
+        Command::Verify { artifact } => {
+            let verified = verify_artifact(&artifact)?;
+            info!(
+                "Verified {} proof of the {} guest in {}",
+                verified.kind,
+                verified.guest,
+                artifact.display()
+            );
+            if let Some(calldata_file) = &verified.calldata_file {
+                info!("Calldata matches {}", calldata_file.display());
+            }
+            if !verified.chained {
+                warn!("The proof does not attest to the MMR history before its first batch");
+            }
+            println!("{}", verified.journal);
+            Ok(())
+        }
+        Command::Inspect { db_file, check } => {
+            let proof_generator = ProofGenerator::new(MMR_GUEST_ELF, MMR_GUEST_ID);
+            let builder = AccumulatorBuilder::open_read_only(&db_file, proof_generator).await?;
+            println!("{}", serde_json::to_string_pretty(&builder.state().await?)?);
+            if check {
+                builder.check_consistency().await?;
+            }
+            Ok(())
+        }
+        Command::VerifyConsistency {
+            batch_size,
+            leaves,
+            seed,
+            from_block,
+            to_block,
+            headers,
+        } => {
+            let leaves = match (from_block, to_block) {
+                (Some(from_block), Some(to_block)) => {
+                    let header_source: Arc<dyn HeaderSource> = match &headers {
+                        Some(headers) => open_header_source(headers).await?,
+                        None => Arc::new(RpcHeaderSource),
+                    };
+                    block_leaves(header_source.as_ref(), from_block, to_block).await?
+                }
+                _ => synthetic_leaves(seed, leaves),
+            };
+            match verify_consistency(&leaves, batch_size as usize).await? {
+                None => {
+                    info!("Guest and host MMRs agree on all {} leaves", leaves.len());
+                    Ok(())
+                }
+                Some(divergence) => Err(eyre::eyre!("MMR implementations diverge: {}", divergence)),
             }
-            None => info!("No proof generated"),
         }
     }
+}
 
-    Ok(())
+/// Read a JSON array of hex encoded felts.
+fn read_leaves(path: &Path) -> Result<Vec<Felt>> {
+    let leaves: Vec<String> = serde_json::from_slice(&std::fs::read(path)?)?;
+    leaves
+        .iter()
+        .map(|leaf| felt::from_hex(leaf).ok_or_else(|| eyre::eyre!("Invalid felt: {}", leaf)))
+        .collect()
 }
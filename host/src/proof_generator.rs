@@ -1,4 +1,4 @@
-pub use crate::types::ProofType;
+pub use crate::types::{GeneratedProof, ProofStats, ProofType};
 use eyre::Result;
 use garaga_rs::{
     calldata::full_proof_with_hints::groth16::{
@@ -6,16 +6,25 @@ use garaga_rs::{
     },
     definitions::CurveID,
 };
-use guest_types::CombinedInput;
+use guest_types::{PhaseCycles, PHASE_CYCLES_FD};
 use risc0_ethereum_contracts::encode_seal;
-use risc0_zkvm::{compute_image_id, default_prover, ExecutorEnv, ProverOpts, VerifierContext};
-use serde::Deserialize;
+use risc0_zkvm::{
+    compute_image_id, default_executor, default_prover, AssumptionReceipt, ExecutorEnv, ProveInfo,
+    ProverOpts, Receipt, VerifierContext,
+};
+use serde::{Deserialize, Serialize};
+use starknet_crypto::Felt;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 use tokio::task;
 use tracing::info;
 
+#[derive(Clone)]
 pub struct ProofGenerator {
     method_elf: &'static [u8],
     method_id: [u32; 8],
+    execute_only: bool,
 }
 
 impl ProofGenerator {
@@ -23,36 +32,123 @@ impl ProofGenerator {
         Self {
             method_elf,
             method_id,
+            execute_only: false,
         }
     }
 
-    /// Generate a standard Stark proof for intermediate batches
-    pub async fn generate_stark_proof(&self, input: &CombinedInput) -> Result<ProofType> {
+    /// Only execute the guest instead of proving it, see `execute`. Meant for
+    /// testing the batch logic and the storage without the cost of proving.
+    pub fn with_execute_only(mut self, execute_only: bool) -> Self {
+        self.execute_only = execute_only;
+        self
+    }
+
+    pub fn is_execute_only(&self) -> bool {
+        self.execute_only
+    }
+
+    /// Image ID of the guest this generator proves.
+    pub fn method_id(&self) -> [u32; 8] {
+        self.method_id
+    }
+
+    /// Run the guest with the executor, without proving it. The journal is
+    /// returned as an unproven `ProofType`.
+    ///
+    /// `assumptions` are resolved against nothing, so they may be unresolved
+    /// claims of previous batches that were not proven either.
+    pub async fn execute<T: Serialize + Clone + Send + 'static>(
+        &self,
+        input: &T,
+        assumptions: Vec<AssumptionReceipt>,
+        side_channel: Option<SideChannel>,
+    ) -> Result<GeneratedProof> {
         let method_elf = self.method_elf;
         let method_id = self.method_id;
         let input = input.clone();
 
+        info!("Executing guest without proving...");
+
+        let proof = task::spawn_blocking(move || -> eyre::Result<GeneratedProof> {
+            let phase_cycles = SideChannel::default();
+            let env = build_env(&input, assumptions, side_channel, phase_cycles.clone())?;
+
+            let started = Instant::now();
+            let session = default_executor()
+                .execute(env, method_elf)
+                .map_err(|e| eyre::eyre!("Execution failed: {}", e))?;
+
+            let stats = ProofStats {
+                total_cycles: session.segments.iter().map(|s| 1u64 << s.po2).sum(),
+                user_cycles: session.segments.iter().map(|s| u64::from(s.cycles)).sum(),
+                segments: session.segments.len(),
+                elapsed_ms: started.elapsed().as_millis() as u64,
+                phase_cycles: phase_cycles.decode_if_written()?,
+            };
+            info!(
+                "Executed {} user cycles ({} total) in {} segments",
+                stats.user_cycles, stats.total_cycles, stats.segments
+            );
+
+            Ok(GeneratedProof {
+                proof: ProofType::Unproven {
+                    journal: session.journal.bytes,
+                    method_id,
+                },
+                stats,
+                succinct_receipt: None,
+            })
+        })
+        .await?
+        .map_err(|e| eyre::eyre!("Spawn blocking task failed: {}", e))?;
+
+        Ok(proof)
+    }
+
+    /// Generate a standard Stark proof for intermediate batches.
+    ///
+    /// `assumptions` are the receipts of previous batches verified by the guest
+    /// through `env::verify`; they are resolved while lifting to a succinct receipt.
+    /// If `side_channel` is set, the guest's stdout is captured into it.
+    pub async fn generate_stark_proof<T: Serialize + Clone + Send + 'static>(
+        &self,
+        input: &T,
+        assumptions: &[Receipt],
+        side_channel: Option<SideChannel>,
+    ) -> Result<GeneratedProof> {
+        let method_elf = self.method_elf;
+        let method_id = self.method_id;
+        let input = input.clone();
+        let assumptions = to_assumptions(assumptions);
+
         info!("Generating STARK proof...");
 
-        let proof = task::spawn_blocking(move || -> eyre::Result<ProofType> {
-            let env = ExecutorEnv::builder()
-                .write(&input)
-                .map_err(|e| eyre::eyre!("Failed to write input: {}", e))?
-                .build()
-                .map_err(|e| eyre::eyre!("Failed to build executor env: {}", e))?;
+        let proof = task::spawn_blocking(move || -> eyre::Result<GeneratedProof> {
+            let phase_cycles = SideChannel::default();
+            let env = build_env(&input, assumptions, side_channel, phase_cycles.clone())?;
 
-            let receipt = default_prover()
-                .prove(env, method_elf)
-                .map_err(|e| eyre::eyre!("Proof generation failed: {}", e))?
-                .receipt;
+            let started = Instant::now();
+            let prove_info = default_prover()
+                .prove_with_ctx(
+                    env,
+                    &VerifierContext::default(),
+                    method_elf,
+                    &ProverOpts::succinct(),
+                )
+                .map_err(|e| eyre::eyre!("Proof generation failed: {}", e))?;
+            let stats = prove_stats(&prove_info, started, &phase_cycles)?;
 
             let image_id = compute_image_id(method_elf)
                 .map_err(|e| eyre::eyre!("Failed to compute image id: {}", e))?;
 
-            Ok(ProofType::Stark {
-                receipt,
-                image_id: image_id.as_bytes().to_vec(),
-                method_id,
+            Ok(GeneratedProof {
+                proof: ProofType::Stark {
+                    receipt: prove_info.receipt,
+                    image_id: image_id.as_bytes().to_vec(),
+                    method_id,
+                },
+                stats,
+                succinct_receipt: None,
             })
         })
         .await?
@@ -61,51 +157,58 @@ impl ProofGenerator {
         Ok(proof)
     }
 
-    /// Generate a Groth16 proof for the final batch
-    pub async fn generate_groth16_proof(&self, input: &CombinedInput) -> Result<ProofType> {
+    /// Generate a Groth16 proof for the final batch. `assumptions` and
+    /// `side_channel` are used as in `generate_stark_proof`.
+    pub async fn generate_groth16_proof<T: Serialize + Clone + Send + 'static>(
+        &self,
+        input: &T,
+        assumptions: &[Receipt],
+        side_channel: Option<SideChannel>,
+    ) -> Result<GeneratedProof> {
         let method_elf = self.method_elf;
         let method_id = self.method_id;
         let input = input.clone();
+        let assumptions = to_assumptions(assumptions);
 
         info!("Generating Groth16 proof...");
 
-        let proof = task::spawn_blocking(move || -> eyre::Result<ProofType> {
-            let env = ExecutorEnv::builder()
-                .write(&input)
-                .map_err(|e| eyre::eyre!("Failed to write input: {}", e))?
-                .build()
-                .map_err(|e| eyre::eyre!("Failed to build executor env: {}", e))?;
+        let proof = task::spawn_blocking(move || -> eyre::Result<GeneratedProof> {
+            let phase_cycles = SideChannel::default();
+            let env = build_env(&input, assumptions, side_channel, phase_cycles.clone())?;
 
-            // Generate with Groth16 options
-            let receipt = default_prover()
+            // Prove a succinct receipt first and compress it, keeping the
+            // succinct receipt for later batches to verify through composition
+            let started = Instant::now();
+            let prover = default_prover();
+            let prove_info = prover
                 .prove_with_ctx(
                     env,
                     &VerifierContext::default(),
                     method_elf,
-                    &ProverOpts::groth16(),
+                    &ProverOpts::succinct(),
                 )
-                .map_err(|e| eyre::eyre!("Proof generation failed: {}", e))?
-                .receipt;
+                .map_err(|e| eyre::eyre!("Proof generation failed: {}", e))?;
+            let receipt = prover
+                .compress(&ProverOpts::groth16(), &prove_info.receipt)
+                .map_err(|e| eyre::eyre!("Groth16 compression failed: {}", e))?;
+            let stats = prove_stats(&prove_info, started, &phase_cycles)?;
 
             receipt
                 .verify(method_id)
                 .map_err(|e| eyre::eyre!("Verification failed: {}", e))?;
 
             // Convert to Groth16
-            let encoded_seal =
-                encode_seal(&receipt).map_err(|e| eyre::eyre!("Failed to encode seal: {}", e))?;
             let image_id = compute_image_id(method_elf)
                 .map_err(|e| eyre::eyre!("Failed to compute image id: {}", e))?;
-            let journal = receipt.journal.bytes.clone();
-
-            let groth16_proof =
-                Groth16Proof::from_risc0(encoded_seal, image_id.as_bytes().to_vec(), journal);
 
             info!("Generating StarkNet calldata...");
-            let calldata = get_groth16_calldata(&groth16_proof, &get_risc0_vk(), CurveID::BN254)
-                .map_err(|e| eyre::eyre!("Failed to generate StarkNet calldata: {}", e))?;
+            let calldata = groth16_calldata(&receipt, image_id.as_bytes())?;
 
-            Ok(ProofType::Groth16 { receipt, calldata })
+            Ok(GeneratedProof {
+                proof: ProofType::Groth16 { receipt, calldata },
+                stats,
+                succinct_receipt: Some(prove_info.receipt),
+            })
         })
         .await?
         .map_err(|e| eyre::eyre!("Spawn blocking task failed: {}", e))?;
@@ -114,10 +217,116 @@ impl ProofGenerator {
     }
 
     pub fn decode_journal<T: for<'a> Deserialize<'a>>(&self, proof: &ProofType) -> Result<T> {
-        let receipt = match proof {
-            ProofType::Stark { receipt, .. } => receipt,
-            ProofType::Groth16 { receipt, .. } => receipt,
-        };
-        Ok(receipt.journal.decode()?)
+        risc0_zkvm::serde::from_slice(proof.journal())
+            .map_err(|e| eyre::eyre!("Failed to decode journal: {}", e))
+    }
+}
+
+/// Starknet calldata of the Groth16 `receipt` of the guest `image_id`, for
+/// the garaga verifier.
+pub fn groth16_calldata(receipt: &Receipt, image_id: &[u8]) -> Result<Vec<Felt>> {
+    let encoded_seal =
+        encode_seal(receipt).map_err(|e| eyre::eyre!("Failed to encode seal: {}", e))?;
+    let journal = receipt.journal.bytes.clone();
+
+    let groth16_proof = Groth16Proof::from_risc0(encoded_seal, image_id.to_vec(), journal);
+    get_groth16_calldata(&groth16_proof, &get_risc0_vk(), CurveID::BN254)
+        .map_err(|e| eyre::eyre!("Failed to generate StarkNet calldata: {}", e))
+}
+
+/// Guest stdout captured by the host, used by the guest to hand over data
+/// that is committed to the journal only through a digest.
+#[derive(Clone, Default)]
+pub struct SideChannel(Arc<Mutex<Vec<u8>>>);
+
+impl SideChannel {
+    /// Decode the value written by the guest with `env::write`.
+    pub fn decode<T: for<'a> Deserialize<'a>>(&self) -> Result<T> {
+        let bytes = self
+            .0
+            .lock()
+            .map_err(|_| eyre::eyre!("Side channel lock poisoned"))?;
+        risc0_zkvm::serde::from_slice(&bytes)
+            .map_err(|e| eyre::eyre!("Failed to decode side channel output: {}", e))
     }
+
+    /// Same as `decode`, or `None` if the guest wrote nothing.
+    pub fn decode_if_written<T: for<'a> Deserialize<'a>>(&self) -> Result<Option<T>> {
+        let is_empty = self
+            .0
+            .lock()
+            .map_err(|_| eyre::eyre!("Side channel lock poisoned"))?
+            .is_empty();
+        if is_empty {
+            return Ok(None);
+        }
+        self.decode().map(Some)
+    }
+}
+
+impl Write for SideChannel {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .lock()
+            .map_err(|_| io::Error::other("Side channel lock poisoned"))?
+            .extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Build the executor environment for a batch, registering the receipts of
+/// previous batches as assumptions for the guest's `env::verify` calls. The
+/// guest's `PhaseCycles` are captured into `phase_cycles`.
+fn build_env<T: Serialize>(
+    input: &T,
+    assumptions: Vec<AssumptionReceipt>,
+    side_channel: Option<SideChannel>,
+    phase_cycles: SideChannel,
+) -> Result<ExecutorEnv<'static>> {
+    let mut builder = ExecutorEnv::builder();
+    for assumption in assumptions {
+        builder.add_assumption(assumption);
+    }
+    if let Some(side_channel) = side_channel {
+        builder.stdout(side_channel);
+    }
+    builder.write_fd(PHASE_CYCLES_FD, phase_cycles);
+
+    builder
+        .write(input)
+        .map_err(|e| eyre::eyre!("Failed to write input: {}", e))?
+        .build()
+        .map_err(|e| eyre::eyre!("Failed to build executor env: {}", e))
+}
+
+fn to_assumptions(receipts: &[Receipt]) -> Vec<AssumptionReceipt> {
+    receipts
+        .iter()
+        .cloned()
+        .map(AssumptionReceipt::from)
+        .collect()
+}
+
+/// Stats of a proof whose proving started at `started`.
+fn prove_stats(
+    prove_info: &ProveInfo,
+    started: Instant,
+    phase_cycles: &SideChannel,
+) -> Result<ProofStats> {
+    let stats = ProofStats {
+        total_cycles: prove_info.stats.total_cycles,
+        user_cycles: prove_info.stats.user_cycles,
+        segments: prove_info.stats.segments,
+        elapsed_ms: started.elapsed().as_millis() as u64,
+        phase_cycles: phase_cycles.decode_if_written::<PhaseCycles>()?,
+    };
+    info!(
+        "Proved {} user cycles ({} total) in {} segments in {} ms",
+        stats.user_cycles, stats.total_cycles, stats.segments, stats.elapsed_ms
+    );
+    Ok(stats)
 }
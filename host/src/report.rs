@@ -0,0 +1,30 @@
+//! JSON report of the batches processed by a run.
+use crate::types::{BatchResult, ProofStats, ProofType};
+use eyre::Result;
+use serde::Serialize;
+use std::path::Path;
+
+#[derive(Serialize)]
+struct BatchReport {
+    start_block: u64,
+    end_block: u64,
+    /// "stark", "groth16" or "unproven", `None` if nothing was generated
+    proof: Option<&'static str>,
+    stats: Option<ProofStats>,
+}
+
+/// Write the stats of `results` to `path` as a JSON array, one entry per batch.
+pub fn write_report(path: &Path, results: &[BatchResult]) -> Result<()> {
+    let report: Vec<BatchReport> = results
+        .iter()
+        .map(|result| BatchReport {
+            start_block: result.start_block,
+            end_block: result.end_block,
+            proof: result.proof.as_ref().map(ProofType::kind),
+            stats: result.stats,
+        })
+        .collect();
+
+    std::fs::write(path, serde_json::to_string_pretty(&report)?)?;
+    Ok(())
+}
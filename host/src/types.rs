@@ -1,3 +1,4 @@
+use guest_types::{felt, BatchSideOutput, HasherKind, JournalFormat, PhaseCycles, ProofKind};
 use risc0_zkvm::Receipt;
 use serde::{Deserialize, Serialize};
 use starknet_crypto::Felt;
@@ -13,6 +14,79 @@ pub enum ProofType {
         receipt: Receipt,
         calldata: Vec<Felt>,
     },
+    /// A batch run with the executor only, see `ProofGenerator::with_execute_only`
+    Unproven {
+        journal: Vec<u8>,
+        method_id: [u32; 8],
+    },
+}
+
+impl ProofType {
+    /// Raw journal committed by the guest.
+    pub fn journal(&self) -> &[u8] {
+        match self {
+            ProofType::Stark { receipt, .. } => &receipt.journal.bytes,
+            ProofType::Groth16 { receipt, .. } => &receipt.journal.bytes,
+            ProofType::Unproven { journal, .. } => journal,
+        }
+    }
+
+    /// "stark", "groth16" or "unproven".
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ProofType::Stark { .. } => "stark",
+            ProofType::Groth16 { .. } => "groth16",
+            ProofType::Unproven { .. } => "unproven",
+        }
+    }
+}
+
+/// A proof and what it took to generate it.
+pub struct GeneratedProof {
+    pub proof: ProofType,
+    pub stats: ProofStats,
+    /// Succinct receipt a Groth16 proof was compressed from, which later
+    /// batches verify instead, a Groth16 receipt not being composable
+    pub succinct_receipt: Option<Receipt>,
+}
+
+/// Cycle and segment counts of a guest run, and how long proving it took.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ProofStats {
+    /// Cycles including the padding of each segment to a power of two
+    pub total_cycles: u64,
+    /// Cycles spent running the guest
+    pub user_cycles: u64,
+    pub segments: usize,
+    /// Wall clock time of proving, or of executing for unproven batches
+    pub elapsed_ms: u64,
+    /// Breakdown reported by the batch guest, `None` for other guests
+    pub phase_cycles: Option<PhaseCycles>,
+}
+
+/// A completed batch as recorded in the `batch_checkpoints` table.
+#[derive(Debug, Clone)]
+pub struct BatchCheckpoint {
+    pub start_block: u64,
+    pub end_block: u64,
+    pub journal: Vec<u8>,
+    pub proof: ProofType,
+    pub peaks: Vec<Felt>,
+    pub elements_count: usize,
+    pub leaves_count: usize,
+    pub journal_format: JournalFormat,
+    pub hasher: HasherKind,
+    /// `None` for batches checkpointed before stats were recorded
+    pub stats: Option<ProofStats>,
+    /// Succinct receipt the next batch chains from, when `proof` is a
+    /// Groth16 proof. `None` for other proofs and older checkpoints.
+    pub chain_receipt: Option<Receipt>,
+    /// `false` for the segments of a parallel build, whose proofs do not
+    /// attest to the MMR before them and cannot be chained from
+    pub chainable: bool,
+    /// `ProofKind::Aggregation` for the checkpoint of the aggregation proof
+    /// recorded after the segments of a parallel build
+    pub proof_kind: ProofKind,
 }
 
 pub struct BatchResult {
@@ -20,6 +94,26 @@ pub struct BatchResult {
     pub end_block: u64,
     // pub mmr_state: MMRState,
     pub proof: Option<ProofType>,
+    pub stats: Option<ProofStats>,
+    /// Image ID of the guest that produced `proof`
+    pub image_id: [u32; 8],
+    /// What a compact journal left out, needed to check it against its
+    /// digest. `None` for full journals.
+    pub side_output: Option<BatchSideOutput>,
+}
+
+/// Summary of an MMR store.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MmrState {
+    pub elements_count: usize,
+    pub leaves_count: usize,
+    #[serde(with = "felt::vec")]
+    pub peaks: Vec<Felt>,
+    #[serde(with = "felt")]
+    pub root: Felt,
+    pub hasher: HasherKind,
+    /// Lowest and highest accumulated block, `None` for an empty MMR
+    pub block_range: Option<(u64, u64)>,
 }
 
 // #[derive(Clone)]
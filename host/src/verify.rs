@@ -0,0 +1,206 @@
+//! Offline verification of the proofs archived by `ArtifactStore`.
+use crate::artifacts::{journal_digest, ArtifactStore};
+use crate::proof_generator::groth16_calldata;
+use crate::types::ProofType;
+use eyre::Result;
+use guest_types::{
+    felt, AggregationOutput, BatchSideOutput, BatchTransition, CompactGuestOutput, GuestOutput,
+};
+use methods::{MMR_AGGREGATE_ID, MMR_GUEST_ID};
+use risc0_zkvm::sha::Digest;
+use risc0_zkvm::Receipt;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// Outcome of `verify_artifact`.
+#[derive(Debug)]
+pub struct VerifiedArtifact {
+    /// "stark" or "groth16"
+    pub kind: &'static str,
+    /// Name of the guest the receipt verified against
+    pub guest: &'static str,
+    /// Decoded journal, as pretty-printed JSON
+    pub journal: String,
+    /// Whether the receipt attests to the whole MMR history, see
+    /// `GuestOutput::chained`
+    pub chained: bool,
+    /// Calldata file the rebuilt calldata was compared with, for Groth16 proofs
+    pub calldata_file: Option<PathBuf>,
+}
+
+/// Verify the receipt stored at `path`, a `.proof` file written by
+/// `ArtifactStore`, against the batch and the aggregation guest, and decode
+/// its journal. The calldata of a Groth16 proof is rebuilt from the receipt
+/// and must be identical to the stored one.
+///
+/// If `path` lies in an artifact directory, its journal is also checked
+/// against the digest recorded in the manifest, and a compact journal is
+/// expanded with its archived side output.
+pub fn verify_artifact(path: &Path) -> Result<VerifiedArtifact> {
+    let (proof, side_output) = load_artifact(path)?;
+    let receipt = match &proof {
+        ProofType::Stark { receipt, .. } | ProofType::Groth16 { receipt, .. } => receipt,
+        ProofType::Unproven { .. } => {
+            return Err(eyre::eyre!(
+                "{} was executed without proving, there is no receipt to verify",
+                path.display()
+            ))
+        }
+    };
+
+    // The batch guest trusts the image IDs it is given for the previous
+    // proof, and commits them: the chain is only ours if they are ours
+    let (guest, image_id, journal, chained) = if receipt.verify(MMR_GUEST_ID).is_ok() {
+        let (transition, journal) = decode_batch_journal(receipt, side_output)?;
+        check_method_ids(&transition)?;
+        ("batch", MMR_GUEST_ID, journal, transition.chained)
+    } else if receipt.verify(MMR_AGGREGATE_ID).is_ok() {
+        let output: AggregationOutput = decode_exact(&receipt.journal.bytes)
+            .ok_or_else(|| eyre::eyre!("Failed to decode aggregation journal"))?;
+        check_method_ids(&output.transition)?;
+        if output.batch_method_id != MMR_GUEST_ID {
+            return Err(eyre::eyre!(
+                "Journal aggregates batch guest {}, expected {}",
+                Digest::from(output.batch_method_id),
+                Digest::from(MMR_GUEST_ID)
+            ));
+        }
+        let (journal, chained) = (to_pretty_json(&output)?, output.transition.chained);
+        ("aggregation", MMR_AGGREGATE_ID, journal, chained)
+    } else {
+        return Err(eyre::eyre!(
+            "Receipt in {} does not verify against the batch or the aggregation guest",
+            path.display()
+        ));
+    };
+
+    let calldata_file = match &proof {
+        ProofType::Groth16 { calldata, .. } => {
+            let rebuilt = groth16_calldata(receipt, Digest::from(image_id).as_bytes())?;
+            if &rebuilt != calldata {
+                return Err(eyre::eyre!(
+                    "Calldata rebuilt from the receipt differs from the calldata in {}",
+                    path.display()
+                ));
+            }
+
+            let calldata_file = path.with_extension("calldata.json");
+            if calldata_file.exists() {
+                let rebuilt: Vec<String> = rebuilt.iter().map(felt::to_hex).collect();
+                if fs::read(&calldata_file)? != serde_json::to_vec_pretty(&rebuilt)? {
+                    return Err(eyre::eyre!(
+                        "Calldata rebuilt from the receipt differs from {}",
+                        calldata_file.display()
+                    ));
+                }
+                Some(calldata_file)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    };
+
+    Ok(VerifiedArtifact {
+        kind: proof.kind(),
+        guest,
+        journal,
+        chained,
+        calldata_file,
+    })
+}
+
+/// Read the proof at `path`, through the manifest of its artifact directory
+/// when there is one, along with its side output. A proof missing from that
+/// manifest is rejected.
+fn load_artifact(path: &Path) -> Result<(ProofType, Option<BatchSideOutput>)> {
+    let path = fs::canonicalize(path)
+        .map_err(|e| eyre::eyre!("Failed to open {}: {}", path.display(), e))?;
+    let artifacts_dir = path.parent().and_then(Path::parent);
+    if let Some(dir) = artifacts_dir.filter(|dir| dir.join("manifest.json").exists()) {
+        let store = ArtifactStore::open(dir)?;
+        let entry = store.manifest().artifacts.iter().find(|entry| {
+            fs::canonicalize(store.dir().join(&entry.proof_file)).is_ok_and(|file| file == path)
+        });
+        return match entry {
+            Some(entry) => Ok((store.load_proof(entry)?, store.load_side_output(entry)?)),
+            None => Err(eyre::eyre!(
+                "{} is not listed in the manifest of {}",
+                path.display(),
+                dir.display()
+            )),
+        };
+    }
+
+    let proof: ProofType = bincode::deserialize(&fs::read(&path)?)
+        .map_err(|e| eyre::eyre!("Failed to read proof from {}: {}", path.display(), e))?;
+    info!(
+        "{} is not in an artifact directory, journal digest {} not checked",
+        path.display(),
+        journal_digest(proof.journal())
+    );
+    Ok((proof, None))
+}
+
+/// Decode a batch journal in whichever format it was committed in, along
+/// with the transition it commits. A compact journal is expanded with
+/// `side_output` when it was archived, which checks its append digest.
+fn decode_batch_journal(
+    receipt: &Receipt,
+    side_output: Option<BatchSideOutput>,
+) -> Result<(BatchTransition, String)> {
+    let journal = &receipt.journal.bytes;
+    if let Some(output) = decode_exact::<GuestOutput>(journal) {
+        let json = to_pretty_json(&output)?;
+        return Ok((output.into(), json));
+    }
+    if let Some(output) = decode_exact::<CompactGuestOutput>(journal) {
+        let Some(side_output) = side_output else {
+            info!("No side output archived, append digest of the compact journal not checked");
+            let json = to_pretty_json(&output)?;
+            return Ok((output.into(), json));
+        };
+        let output = output.expand(side_output)?;
+        let json = to_pretty_json(&output)?;
+        return Ok((output.into(), json));
+    }
+    Err(eyre::eyre!("Failed to decode batch journal"))
+}
+
+/// Check the guest IDs a transition commits to, which its previous proofs
+/// were required to come from.
+fn check_method_ids(transition: &BatchTransition) -> Result<()> {
+    if transition.method_id != MMR_GUEST_ID {
+        return Err(eyre::eyre!(
+            "Journal commits batch guest {}, expected {}",
+            Digest::from(transition.method_id),
+            Digest::from(MMR_GUEST_ID)
+        ));
+    }
+    if transition.aggregation_method_id != MMR_AGGREGATE_ID {
+        return Err(eyre::eyre!(
+            "Journal commits aggregation guest {}, expected {}",
+            Digest::from(transition.aggregation_method_id),
+            Digest::from(MMR_AGGREGATE_ID)
+        ));
+    }
+    Ok(())
+}
+
+/// Decode `journal` as a `T`, only if it encodes exactly one `T` and nothing
+/// else, so that a compact journal is not mistaken for a prefix of a full one.
+fn decode_exact<T: Serialize + for<'a> Deserialize<'a>>(journal: &[u8]) -> Option<T> {
+    let value: T = risc0_zkvm::serde::from_slice(journal).ok()?;
+    let encoded: Vec<u8> = risc0_zkvm::serde::to_vec(&value)
+        .ok()?
+        .into_iter()
+        .flat_map(u32::to_le_bytes)
+        .collect();
+    (encoded == journal).then_some(value)
+}
+
+fn to_pretty_json<T: Serialize>(value: &T) -> Result<String> {
+    Ok(serde_json::to_string_pretty(value)?)
+}
@@ -0,0 +1,60 @@
+// batch_journal.rs
+use guest_types::{
+    AggregationOutput, BatchProof, BatchTransition, CompactGuestOutput, GuestOutput, JournalFormat,
+    ProofKind,
+};
+use risc0_zkvm::guest::env;
+
+/// Decode the state transition committed in the journal of a batch proof,
+/// according to its format. Panics on a malformed journal.
+pub fn decode_transition(proof: &BatchProof) -> BatchTransition {
+    match proof.format {
+        JournalFormat::Full => {
+            let output: GuestOutput =
+                risc0_zkvm::serde::from_slice(&proof.journal).expect("Invalid batch journal");
+            output.into()
+        }
+        JournalFormat::Compact => {
+            let output: CompactGuestOutput =
+                risc0_zkvm::serde::from_slice(&proof.journal).expect("Invalid batch journal");
+            output.into()
+        }
+    }
+}
+
+/// Verify the proof of the batches preceding the current ones through
+/// composition, and decode the transition it attests to. The host adds its
+/// receipt as an assumption, which is resolved outside of the guest.
+///
+/// A batch proof must come from `batch_method_id`. An aggregation proof must
+/// come from `aggregation_method_id`, over batches of `batch_method_id`.
+pub fn verify_previous_proof(
+    proof: &BatchProof,
+    batch_method_id: [u32; 8],
+    aggregation_method_id: [u32; 8],
+) -> BatchTransition {
+    match proof.kind {
+        ProofKind::Batch => {
+            assert_eq!(
+                proof.method_id, batch_method_id,
+                "Previous batch was proven by another guest"
+            );
+            env::verify(proof.method_id, &proof.journal).expect("Invalid previous proof");
+            decode_transition(proof)
+        }
+        ProofKind::Aggregation => {
+            assert_eq!(
+                proof.method_id, aggregation_method_id,
+                "Previous batches were aggregated by another guest"
+            );
+            env::verify(proof.method_id, &proof.journal).expect("Invalid previous proof");
+            let output: AggregationOutput =
+                risc0_zkvm::serde::from_slice(&proof.journal).expect("Invalid aggregation journal");
+            assert_eq!(
+                output.batch_method_id, batch_method_id,
+                "Previous batches were proven by another guest"
+            );
+            output.transition
+        }
+    }
+}
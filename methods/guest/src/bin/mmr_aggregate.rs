@@ -0,0 +1,60 @@
+// mmr_aggregate.rs
+use guest_types::{chain_transitions, merge_block_ranges, AggregationInput, AggregationOutput};
+use mmr_guest::batch_journal::{decode_transition, verify_previous_proof};
+use risc0_zkvm::guest::env;
+
+fn main() {
+    // Read aggregation input
+    let input: AggregationInput = env::read();
+
+    // Verify every batch through composition. The host adds their receipts
+    // as assumptions, which are resolved outside of this guest.
+    let mut transitions = Vec::with_capacity(input.batches.len());
+    for (i, batch) in input.batches.iter().enumerate() {
+        assert_eq!(
+            batch.method_id, input.batch_method_id,
+            "Batch {} was proven by another guest",
+            i
+        );
+        env::verify(batch.method_id, &batch.journal).expect("Invalid batch proof");
+
+        // The batch guest also committed the ID it chained its previous proof with
+        let transition = decode_transition(batch);
+        assert_eq!(
+            transition.method_id, input.batch_method_id,
+            "Batch {} continues a batch of another guest",
+            i
+        );
+        transitions.push(transition);
+    }
+
+    // The batches must follow each other without gaps
+    let mut transition = match chain_transitions(&transitions) {
+        Ok(transition) => transition,
+        Err(e) => panic!("Invalid batch sequence: {}", e),
+    };
+
+    // The first batch must continue the proven MMR, if there is one, proven
+    // by a batch or by an earlier aggregation. The batches committed the ID
+    // of the aggregation guest, which verifiers check.
+    if let Some(previous) = &input.previous_proof {
+        let previous = verify_previous_proof(
+            previous,
+            input.batch_method_id,
+            transition.aggregation_method_id,
+        );
+        assert!(
+            previous.connects_to(&transition),
+            "Batches do not continue the previous proof"
+        );
+        transition.chained = previous.chained;
+        transition.chain_block_range =
+            merge_block_ranges(previous.chain_block_range, transition.chain_block_range);
+    }
+
+    env::commit(&AggregationOutput {
+        batch_method_id: input.batch_method_id,
+        batch_count: transitions.len(),
+        transition,
+    });
+}
@@ -0,0 +1,54 @@
+// mmr_inclusion.rs
+use guest_mmr::block_hash_element;
+use guest_types::header_rlp::{block_hashes_match, compute_block_hash};
+use guest_types::{verify_inclusion_proof, InclusionInput, InclusionOutput};
+use risc0_zkvm::guest::env;
+
+fn main() {
+    // Read inclusion input
+    let input: InclusionInput = env::read();
+    let header = &input.header;
+
+    // Recompute the leaf from the header
+    let block_hash = match compute_block_hash(header, input.requests_hash.as_deref()) {
+        Ok(hash) => hash,
+        Err(e) => panic!("Failed to hash block {}: {}", header.number, e),
+    };
+    assert!(
+        block_hashes_match(&block_hash, &header.block_hash),
+        "Invalid block hash for block {}",
+        header.number
+    );
+    let element = match block_hash_element(input.proof.hasher, &block_hash) {
+        Ok(element) => element,
+        Err(e) => panic!("Invalid block hash for block {}: {}", header.number, e),
+    };
+    assert!(
+        element == input.proof.element_hash,
+        "Proof element does not match block hash of block {}",
+        header.number
+    );
+
+    // Check the path up to the peaks and the root
+    match verify_inclusion_proof(&input.proof, &input.root) {
+        Ok(true) => {}
+        Ok(false) => panic!("Block {} is not included in the MMR root", header.number),
+        Err(e) => panic!("Invalid inclusion proof: {}", e),
+    }
+
+    // Create output
+    let output = InclusionOutput {
+        root: input.root,
+        elements_count: input.proof.elements_count,
+        block_number: header.number as u64,
+        block_hash,
+        parent_hash: header.parent_hash.clone(),
+        state_root: header.state_root.clone(),
+        transaction_root: header.transaction_root.clone(),
+        receipts_root: header.receipts_root.clone(),
+        timestamp: header.timestamp.clone(),
+    };
+
+    // Commit the output
+    env::commit(&output);
+}
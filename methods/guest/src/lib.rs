@@ -1 +1 @@
-pub mod guest_mmr;
+pub mod batch_journal;
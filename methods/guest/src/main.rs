@@ -1,67 +1,151 @@
 // main.rs
 use block_validity::utils::are_blocks_and_chain_valid;
-use risc0_zkvm::guest::env;
-mod guest_mmr;
-use guest_mmr::GuestMMR;
-use guest_types::{CombinedInput, GuestOutput};
+use guest_mmr::{append_batch_profiled, block_hash_element};
+use guest_types::header_rlp::{block_hashes_match, compute_block_hash};
+use guest_types::{
+    merge_block_ranges, AppendMode, BatchTransition, CombinedInput, JournalFormat, PhaseCycles,
+    PHASE_CYCLES_FD,
+};
+use mmr_guest::batch_journal::verify_previous_proof;
+use risc0_zkvm::guest::env::{self, FdWriter, Write};
 
 fn main() {
     // Read combined input
     let input: CombinedInput = env::read();
+    let verification_start = env::cycle_count();
 
-    // Verify previous batch proofs
-    for proof in &input.mmr_input.previous_proofs {
-        // Verify each previous proof
-        proof
-            .receipt
-            .verify(proof.method_id)
-            .expect("Invalid previous proof");
+    // Only a batch starting from the empty MMR is chained without a previous
+    // proof, so an empty MMR must not come with leaves or peaks
+    if input.mmr_input.elements_count == 0 {
+        assert!(
+            input.mmr_input.starts_empty(),
+            "Empty MMR with leaves or peaks"
+        );
     }
-    // Verify block headers
-    assert!(
-        are_blocks_and_chain_valid(&input.headers),
-        "Invalid block headers"
-    );
 
-    // Initialize MMR with previous state
-    let mut mmr = GuestMMR::new(
-        input.mmr_input.initial_peaks,
-        input.mmr_input.elements_count,
-        input.mmr_input.leaves_count,
-    );
+    // Verify the previous proof, of a batch or of aggregated batches, which
+    // already attests to every batch before it, so only one is needed. It
+    // must come from this guest, or the aggregation guest, whose IDs are
+    // committed below for verifiers to check. That it chains into this batch
+    // is checked once the batch is appended.
+    let previous = input.mmr_input.previous_proofs.as_ref().map(|proof| {
+        verify_previous_proof(
+            proof,
+            input.mmr_input.method_id,
+            input.mmr_input.aggregation_method_id,
+        )
+    });
 
-    let mut append_results = Vec::new();
+    let headers_start = env::cycle_count();
+    let new_elements = &input.mmr_input.new_elements;
 
-    // Append block hashes to MMR
-    for (_, header) in input.headers.iter().enumerate() {
-        let block_hash = header.block_hash.clone();
-        match mmr.append(block_hash) {
-            Ok(result) => {
-                append_results.push(result);
-            }
-            Err(e) => {
-                assert!(false, "MMR append failed: {:?}", e);
+    let block_range = match input.mode {
+        AppendMode::BlockHashes => {
+            // The headers must be consecutive blocks, in ascending order for
+            // forward builds or descending order for backward builds, so that
+            // the committed range is exactly the blocks appended
+            assert!(!input.headers.is_empty(), "No block headers provided");
+            let ascending = input
+                .headers
+                .windows(2)
+                .all(|pair| pair[1].number == pair[0].number + 1);
+            let descending = input
+                .headers
+                .windows(2)
+                .all(|pair| pair[1].number + 1 == pair[0].number);
+            assert!(ascending || descending, "Block headers are not consecutive");
+
+            // Verify block headers, whose parent links run upwards
+            let chain_valid = if ascending {
+                are_blocks_and_chain_valid(&input.headers)
+            } else {
+                let mut headers = input.headers.clone();
+                headers.reverse();
+                are_blocks_and_chain_valid(&headers)
+            };
+            assert!(chain_valid, "Invalid block headers");
+
+            // Every element must be the hash recomputed from the matching header
+            assert_eq!(
+                new_elements.len(),
+                input.headers.len(),
+                "Elements and headers count mismatch"
+            );
+            for (i, (element, header)) in new_elements.iter().zip(input.headers.iter()).enumerate()
+            {
+                let requests_hash = input.requests_hashes.get(i).and_then(|h| h.as_deref());
+                let block_hash = match compute_block_hash(header, requests_hash) {
+                    Ok(hash) => hash,
+                    Err(e) => panic!("Failed to hash block {}: {}", header.number, e),
+                };
+
+                assert!(
+                    block_hashes_match(&block_hash, &header.block_hash),
+                    "Invalid block hash for block {}",
+                    header.number
+                );
+                let expected_element = match block_hash_element(input.hasher, &block_hash) {
+                    Ok(element) => element,
+                    Err(e) => panic!("Invalid block hash for block {}: {}", header.number, e),
+                };
+                assert!(
+                    *element == expected_element,
+                    "Element does not match block hash of block {}",
+                    header.number
+                );
             }
-        }
-    }
 
-    // Get final peaks
-    let final_peaks = match mmr.get_peaks(Default::default()) {
-        Ok(peaks) => peaks,
-        Err(e) => {
-            assert!(false, "Failed to get final peaks: {:?}", e);
-            vec![] // This line will never be reached due to assert
+            let numbers = input.headers.iter().map(|header| header.number as u64);
+            let start_block = numbers.clone().min().unwrap();
+            let end_block = numbers.max().unwrap();
+            Some((start_block, end_block))
+        }
+        AppendMode::GenericLeaves => {
+            assert!(
+                input.headers.is_empty(),
+                "Headers are not allowed in generic leaves mode"
+            );
+            None
         }
     };
 
-    // Create output
-    let output = GuestOutput {
-        final_peaks,
-        elements_count: mmr.get_elements_count(),
-        leaves_count: mmr.get_leaves_count(),
-        append_results,
+    let headers_end = env::cycle_count();
+
+    // Append the elements to the MMR
+    let (mut output, mmr_cycles) =
+        match append_batch_profiled(input.hasher, input.mmr_input, block_range, env::cycle_count) {
+            Ok(result) => result,
+            Err(e) => panic!("MMR append failed: {}", e),
+        };
+
+    // The previous proof must end exactly where this batch begins, and this
+    // batch must continue its blocks, with the same rules as the aggregation
+    if let Some(previous) = previous {
+        assert!(
+            previous.connects_to(&BatchTransition::from(&output)),
+            "Batch does not continue the previous proof"
+        );
+        output.chained = previous.chained;
+        output.chain_block_range =
+            merge_block_ranges(previous.chain_block_range, output.block_range);
+    }
+
+    // Report where the cycles went, outside of the journal
+    let cycles = PhaseCycles {
+        previous_proof_verification: headers_start - verification_start,
+        header_validation: headers_end - headers_start,
+        ..mmr_cycles
     };
+    FdWriter::new(PHASE_CYCLES_FD, |_| {}).write(&cycles);
 
-    // Commit the output
-    env::commit(&output);
+    // Commit the output. In compact format only the state and a digest are
+    // committed, the rest is handed to the host through stdout.
+    match input.journal_format {
+        JournalFormat::Full => env::commit(&output),
+        JournalFormat::Compact => {
+            let (journal, side_output) = output.into_compact();
+            env::write(&side_output);
+            env::commit(&journal);
+        }
+    }
 }